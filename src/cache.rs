@@ -0,0 +1,124 @@
+//! The cached copies of feeds, one file per feed named by its title. A cache is replaced
+//! atomically, by writing a temporary file in a `.tmp` directory and renaming it into place, so
+//! that an interrupted update never leaves a truncated cache behind. Previous versions are kept in
+//! a `.backup` directory as `<title>.1` (the most recent), `<title>.2` and so on.
+//!
+//! Caches and backups may be stored compressed with gzip. Reading recognizes compressed files by
+//! their magic number, so compression can be switched on or off at any time.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::Path;
+
+/// The first bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns the path of the cached copy of the feed titled `title`.
+pub fn path(dir_path: &str, title: &str) -> String {
+    format!("{}/{}", dir_path, title)
+}
+
+/// Returns the path of the `generation`th most recent backup of the feed titled `title`.
+pub fn backup_path(dir_path: &str, title: &str, generation: usize) -> String {
+    format!("{}/.backup/{}.{}", dir_path, title, generation)
+}
+
+/// Reads the cached copy of the feed titled `title`, decompressing it if need be.
+pub fn read(dir_path: &str, title: &str) -> io::Result<String> {
+    read_file(&path(dir_path, title))
+}
+
+/// Replaces the cached copy of the feed titled `title` with `text`, keeping up to `retention`
+/// previous versions as backups. The new copy is compressed if `compress` is set.
+pub fn write(dir_path: &str, title: &str, text: &str, retention: usize, compress: bool) -> io::Result<()> {
+    fs::create_dir_all(format!("{}/.tmp", dir_path))?;
+    let temporary = format!("{}/.tmp/{}", dir_path, title);
+    let file = File::create(&temporary)?;
+    let file = if compress {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(text.as_bytes())?;
+        encoder.finish()?
+    } else {
+        let mut file = file;
+        file.write_all(text.as_bytes())?;
+        file
+    };
+    file.sync_all()?;
+    drop(file);
+
+    let current = path(dir_path, title);
+    if retention > 0 && Path::new(&current).exists() {
+        fs::create_dir_all(format!("{}/.backup", dir_path))?;
+        for generation in (1..retention).rev() {
+            ignore_not_found(fs::rename(
+                backup_path(dir_path, title, generation),
+                backup_path(dir_path, title, generation + 1),
+            ))?;
+        }
+        let latest = backup_path(dir_path, title, 1);
+        ignore_not_found(fs::remove_file(&latest))?;
+        // The cache stays in place until the rename below, so the backup is a link or a copy.
+        if fs::hard_link(&current, &latest).is_err() {
+            fs::copy(&current, &latest)?;
+        }
+    }
+    // Backups beyond the retention, such as after it was lowered, are dropped.
+    let mut generation = retention + 1;
+    while fs::remove_file(backup_path(dir_path, title, generation)).is_ok() {
+        generation += 1;
+    }
+    fs::rename(&temporary, &current)
+}
+
+/// Brings back the `generation`th most recent backup of the feed titled `title` as its cached
+/// copy and returns its text. The replaced version becomes the most recent backup, so that a
+/// restore can itself be undone.
+pub fn restore(dir_path: &str, title: &str, generation: usize, retention: usize, compress: bool) -> io::Result<String> {
+    let text = read_file(&backup_path(dir_path, title, generation))?;
+    write(dir_path, title, &text, retention.max(1), compress)?;
+    Ok(text)
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return String::from_utf8(bytes).map_err(|err| io::Error::new(ErrorKind::InvalidData, err));
+    }
+    let mut text = String::new();
+    GzDecoder::new(&bytes[..]).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn cache_backups_rotate() {
+        let dir = &testing::temp_dir("cache");
+        for (version, compress) in [("one", false), ("two", true), ("three", false)].iter() {
+            write(dir, "npr", version, 2, *compress).unwrap();
+        }
+        assert_eq!(read(dir, "npr").unwrap(), "three");
+        assert_eq!(read_file(&backup_path(dir, "npr", 1)).unwrap(), "two");
+        assert_eq!(read_file(&backup_path(dir, "npr", 2)).unwrap(), "one");
+        assert_eq!(restore(dir, "npr", 1, 2, true).unwrap(), "two");
+        assert!(fs::read(path(dir, "npr")).unwrap().starts_with(&GZIP_MAGIC));
+        assert_eq!(read(dir, "npr").unwrap(), "two");
+        assert_eq!(read_file(&backup_path(dir, "npr", 1)).unwrap(), "three");
+        write(dir, "npr", "four", 0, false).unwrap();
+        assert!(!Path::new(&backup_path(dir, "npr", 1)).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
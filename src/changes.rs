@@ -0,0 +1,204 @@
+//! What the latest update of each feed changed, kept per feed in a `.changes` directory next to
+//! the cached feeds. Entries are told apart by their references, and told to have been edited by
+//! their digests.
+//!
+//! Each file lists one changed entry per line: `kind<TAB>reference<TAB>date<TAB>title`. Dates and
+//! titles are kept so that removed entries, which are no longer cached, can still be shown.
+
+use crate::{Entry, Feed};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::str::FromStr;
+
+/// How an entry changed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    /// The entry is new.
+    Added,
+    /// The entry was dropped from the feed.
+    Removed,
+    /// The entry was edited.
+    Modified,
+}
+
+impl ChangeKind {
+    /// A short lowercase name for the kind of change.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Modified => "modified",
+        }
+    }
+}
+
+impl FromStr for ChangeKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "added" => Ok(ChangeKind::Added),
+            "removed" => Ok(ChangeKind::Removed),
+            "modified" => Ok(ChangeKind::Modified),
+            _ => Err(format!("unknown kind of change: {}", text)),
+        }
+    }
+}
+
+/// A single changed entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// How the entry changed.
+    pub kind: ChangeKind,
+    /// The reference of the entry, as returned by `Entry::reference()`.
+    pub reference: String,
+    /// The date of the entry, exactly as given in the feed.
+    pub date: Option<String>,
+    /// The title of the entry.
+    pub title: String,
+}
+
+impl Change {
+    /// Records how `entry` changed. Whitespace in its date and title is folded into single spaces,
+    /// so that they fit on a line.
+    fn new(kind: ChangeKind, entry: &Entry) -> Self {
+        let fold = |text: &str| text.split_whitespace().collect::<Vec<_>>().join(" ");
+        Self {
+            kind,
+            reference: entry.reference(),
+            date: entry.date.as_deref().map(fold),
+            title: fold(&entry.title),
+        }
+    }
+}
+
+/// The changes the latest update made to a single feed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedChanges(pub Vec<Change>);
+
+impl FeedChanges {
+    /// Compares the previously cached version of a feed, if there was one, with the new one.
+    /// Added and modified entries come first, in the order of the new version, followed by the
+    /// removed ones in the order of the old version.
+    pub fn compare(old: Option<&Feed>, new: &Feed) -> Self {
+        let old_entries: Vec<Entry> = old.into_iter().flat_map(Feed::entries).collect();
+        let digests: HashMap<String, String> = old_entries
+            .iter()
+            .map(|entry| (entry.reference(), entry.digest()))
+            .collect();
+        let mut references = HashSet::new();
+        let mut changes = Vec::new();
+        for entry in new.entries() {
+            let reference = entry.reference();
+            let kind = match digests.get(&reference) {
+                None => Some(ChangeKind::Added),
+                Some(digest) if *digest != entry.digest() => Some(ChangeKind::Modified),
+                Some(_) => None,
+            };
+            if let Some(kind) = kind {
+                changes.push(Change::new(kind, &entry));
+            }
+            references.insert(reference);
+        }
+        for entry in &old_entries {
+            if !references.contains(&entry.reference()) {
+                changes.push(Change::new(ChangeKind::Removed, entry));
+            }
+        }
+        Self(changes)
+    }
+
+    /// Reads the changes to the feed titled `title` from the feed directory `dir_path`, returning
+    /// `None` if the latest attempt to update that feed did not update it.
+    pub fn load(dir_path: &str, title: &str) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(Self::path(dir_path, title)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let changes = text
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                let kind = fields.next()?.parse().ok()?;
+                let reference = String::from(fields.next()?);
+                let date = Some(String::from(fields.next()?)).filter(|date| !date.is_empty());
+                let title = String::from(fields.next()?);
+                Some(Change { kind, reference, date, title })
+            })
+            .collect();
+        Ok(Some(Self(changes)))
+    }
+
+    /// Writes the changes to the feed titled `title` into the feed directory `dir_path`.
+    pub fn save(&self, dir_path: &str, title: &str) -> io::Result<()> {
+        fs::create_dir_all(format!("{}/.changes", dir_path))?;
+        let mut text = String::new();
+        for change in &self.0 {
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                change.kind.name(),
+                change.reference,
+                change.date.as_deref().unwrap_or(""),
+                change.title,
+            ));
+        }
+        fs::write(Self::path(dir_path, title), text)
+    }
+
+    /// Forgets the changes to the feed titled `title`, such as when an update skips the feed or
+    /// fails, so that changes from an earlier update are not mistaken for new ones.
+    pub fn clear(dir_path: &str, title: &str) -> io::Result<()> {
+        match fs::remove_file(Self::path(dir_path, title)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(dir_path: &str, title: &str) -> String {
+        format!("{}/.changes/{}", dir_path, title)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing;
+
+    fn feed(items: &[(&str, &str)]) -> Feed {
+        let items: String = items
+            .iter()
+            .map(|(id, title)| format!("<item><guid>{}</guid><title>{}</title><pubDate>2021-08-09</pubDate></item>", id, title))
+            .collect();
+        Feed::new(format!("<rss><channel>{}</channel></rss>", items))
+    }
+
+    #[test]
+    fn changes_compared() {
+        let old = feed(&[("2", "Second"), ("1", "First")]);
+        let new = feed(&[("3", "Third\n  Entry"), ("2", "Second, edited")]);
+        let changes = FeedChanges::compare(Some(&old), &new);
+        let summary: Vec<(ChangeKind, &str)> = changes.0
+            .iter()
+            .map(|change| (change.kind, &change.title[..]))
+            .collect();
+        assert_eq!(summary, vec![
+            (ChangeKind::Added, "Third Entry"),
+            (ChangeKind::Modified, "Second, edited"),
+            (ChangeKind::Removed, "First"),
+        ]);
+        assert_eq!(changes.0[2].reference, old.entries().nth(1).unwrap().reference());
+        assert_eq!(FeedChanges::compare(None, &old).0.len(), 2);
+        assert!(FeedChanges::compare(Some(&old), &old).0.is_empty());
+
+        let dir = &testing::temp_dir("changes");
+        changes.save(dir, "npr").unwrap();
+        assert_eq!(FeedChanges::load(dir, "npr").unwrap(), Some(changes));
+        assert_eq!(FeedChanges::load(dir, "neovim").unwrap(), None);
+        FeedChanges::clear(dir, "npr").unwrap();
+        FeedChanges::clear(dir, "neovim").unwrap();
+        assert_eq!(FeedChanges::load(dir, "npr").unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+//! Decoding of fetched feeds into UTF-8, whatever their original character encoding.
+//!
+//! The encoding is taken, in order of precedence (RFC 7303, section 3.3), from a byte order
+//! mark, from the `charset` parameter of the `Content-Type` header, and from the `encoding` of
+//! the XML declaration, defaulting to UTF-8. Since the decoded text is UTF-8, its XML
+//! declaration is rewritten to say so; otherwise the XML parser would decode it a second time.
+
+use encoding_rs::{Encoding, UTF_8};
+
+/// Decodes the body of a response with the given `Content-Type` header, if any, into UTF-8.
+/// Malformed sequences are replaced with U+FFFD rather than failing the whole feed.
+pub fn decode(bytes: &[u8], content_type: Option<&str>) -> String {
+    let encoding = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .or_else(|| content_type.and_then(charset_label).and_then(|label| Encoding::for_label(label.as_bytes())))
+        .or_else(|| declared_label(bytes).and_then(|label| Encoding::for_label(label.as_bytes())))
+        .unwrap_or(UTF_8);
+    let (text, _, _) = encoding.decode(bytes);
+    declare_utf8(&text)
+}
+
+/// Extracts the `charset` parameter of a `Content-Type` header value.
+fn charset_label(content_type: &str) -> Option<String> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|label| !label.is_empty())
+}
+
+/// Extracts the `encoding` of the XML declaration at the start of a document, which is written in
+/// ASCII whatever the encoding of the rest.
+fn declared_label(bytes: &[u8]) -> Option<String> {
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    let declaration = &head[..head.find("?>")?];
+    if !declaration.trim_start().starts_with("<?xml") {
+        return None;
+    }
+    let (_, value) = declaration.split_once("encoding")?;
+    let value = value.trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|quote| *quote == '"' || *quote == '\'')?;
+    let value = &value[1..];
+    Some(String::from(&value[..value.find(quote)?]))
+}
+
+/// Rewrites the `encoding` of the XML declaration, if there is one, to UTF-8.
+fn declare_utf8(text: &str) -> String {
+    let end = match text.find("?>") {
+        Some(end) if text.trim_start().starts_with("<?xml") => end,
+        _ => return String::from(text),
+    };
+    let declaration = &text[..end];
+    let label = match declared_label(&text.as_bytes()[..end + 2]) {
+        Some(label) if !label.eq_ignore_ascii_case("utf-8") => label,
+        _ => return String::from(text),
+    };
+    let declaration = declaration
+        .replacen(&format!("\"{}\"", label), "\"utf-8\"", 1)
+        .replacen(&format!("'{}'", label), "'utf-8'", 1);
+    format!("{}{}", declaration, &text[end..])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_declared() {
+        let bytes = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><rss><title>Caf\xe9</title></rss>";
+        assert_eq!(decode(bytes, None), "<?xml version=\"1.0\" encoding=\"utf-8\"?><rss><title>Café</title></rss>");
+        assert_eq!(decode(bytes, Some("application/rss+xml; charset=utf-8")), "<?xml version=\"1.0\" encoding=\"utf-8\"?><rss><title>Caf\u{fffd}</title></rss>");
+    }
+
+    #[test]
+    fn decode_header_and_bom() {
+        let shift_jis = b"<rss><title>\x93\xfa\x96\x7b</title></rss>";
+        assert_eq!(decode(shift_jis, Some("text/xml; charset=\"Shift_JIS\"")), "<rss><title>日本</title></rss>");
+        let bom = b"\xef\xbb\xbf<?xml version='1.0' encoding='windows-1252'?><rss/>";
+        assert_eq!(decode(bom, Some("text/xml; charset=iso-8859-1")), "<?xml version='1.0' encoding='utf-8'?><rss/>");
+    }
+}
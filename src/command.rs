@@ -0,0 +1,47 @@
+//! Running of user-supplied shell commands, such as those producing secrets, feeds or filtered
+//! feeds.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Runs `command` with `sh -c`, feeding it `input` on standard input if given, and returns its
+/// standard output. Its standard error is passed through. Commands which cannot be started or
+/// exit unsuccessfully are errors.
+pub fn run(command: &str, input: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|err| format!("cannot run `{}`: {}", command, err))?;
+    let output = thread::scope(|scope| {
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            // Written from another thread, so that a command producing output before it has read
+            // all of its input cannot deadlock. Commands which ignore their input are fine too.
+            scope.spawn(move || {
+                let _ = stdin.write_all(input);
+            });
+        }
+        child.wait_with_output()
+    });
+    let output = output.map_err(|err| format!("cannot run `{}`: {}", command, err))?;
+    if !output.status.success() {
+        return Err(format!("`{}` failed with {}", command, output.status));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_with_input() {
+        assert_eq!(run("tr a-z A-Z", Some(b"rss")), Ok(b"RSS".to_vec()));
+        assert_eq!(run("echo feed", None), Ok(b"feed\n".to_vec()));
+        assert!(run("exit 3", None).is_err());
+    }
+}
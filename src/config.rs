@@ -0,0 +1,397 @@
+//! The optional `config.toml` in the configuration directory, holding global settings and
+//! overrides for individual feeds. A missing file is the same as an empty one.
+//!
+//! ```toml
+//! user_agent = "koifeed"
+//! timeout = 30
+//! proxy = "socks5h://proxy.example.com:1080"
+//! concurrency = 4
+//! retries = 3
+//! compress = true
+//! output_format = "tsv"
+//! date_format = "%d.%m.%Y"
+//!
+//! [feeds.npr]
+//! disabled = true
+//!
+//! [feeds.neovim]
+//! interval = "1d"
+//!
+//! [feeds."https://ci.example.com/builds.atom"]
+//! headers = { X-Api-Key = "..." }
+//! proxy = ""
+//! ca_bundle = "/etc/ssl/corporate.pem"
+//! filters = ["sed 's/&nbsp;/\\&#160;/g'", "xmllint --recover -"]
+//!
+//! [feeds.tracker.auth]
+//! scheme = "basic"
+//! username = "ty"
+//! secret_command = "pass show tracker"
+//!
+//! [[rules]]
+//! tags = ["news"]
+//! older_than = "30d"
+//! ```
+
+use crate::command;
+use crate::range::parse_duration;
+use crate::rules::Rules;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::str::FromStr;
+
+/// How listings are printed.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Tab-separated columns under a header line, with a heading for each feed.
+    #[default]
+    Table,
+    /// Tab-separated columns only, for consumption by other programs.
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "table" => Ok(OutputFormat::Table),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(format!("invalid output format: {}", text)),
+        }
+    }
+}
+
+/// Global settings.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The User-Agent header sent with every request.
+    pub user_agent: Option<String>,
+    /// Seconds to wait for each feed before giving up on it.
+    pub timeout: Option<u64>,
+    /// Extra headers sent with every request.
+    pub headers: BTreeMap<String, String>,
+    /// The HTTP, HTTPS or SOCKS proxy to send requests through, or an empty string to bypass any
+    /// proxy given by the environment.
+    pub proxy: Option<String>,
+    /// A PEM file of certificate authorities to trust in addition to the system ones.
+    pub ca_bundle: Option<String>,
+    /// A PEM file holding the certificate to identify with to servers asking for one.
+    pub client_cert: Option<String>,
+    /// A PEM file holding the PKCS #8 private key of `client_cert`.
+    pub client_key: Option<String>,
+    /// How many feeds `update` fetches at once.
+    pub concurrency: usize,
+    /// How many more times to try fetching a feed after a transient failure.
+    pub retries: u32,
+    /// How many previous versions of each cached feed to keep.
+    pub retention: usize,
+    /// Whether to store cached feeds compressed with gzip.
+    pub compress: bool,
+    /// How listings are printed unless `--output-format` is given.
+    pub output_format: OutputFormat,
+    /// The `strftime`-style format of dates in listings.
+    pub date_format: String,
+    /// Overrides for individual feeds, keyed by feed title or by `xmlUrl`.
+    pub feeds: BTreeMap<String, FeedConfig>,
+    /// Rules hiding entries from listings and searches.
+    pub rules: Vec<Rule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            user_agent: None,
+            timeout: None,
+            headers: BTreeMap::new(),
+            proxy: None,
+            ca_bundle: None,
+            client_cert: None,
+            client_key: None,
+            concurrency: 1,
+            retries: 2,
+            retention: 1,
+            compress: false,
+            output_format: OutputFormat::default(),
+            date_format: String::from("%Y-%m-%d"),
+            feeds: BTreeMap::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Settings for a single feed, overriding the global ones where both exist.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedConfig {
+    /// Skip this feed when updating.
+    pub disabled: bool,
+    /// The User-Agent header sent when fetching this feed.
+    pub user_agent: Option<String>,
+    /// Seconds to wait for this feed before giving up on it.
+    pub timeout: Option<u64>,
+    /// Extra headers sent when fetching this feed, in addition to the global ones.
+    pub headers: BTreeMap<String, String>,
+    /// The proxy to fetch this feed through, or an empty string to fetch it directly.
+    pub proxy: Option<String>,
+    /// A PEM file of certificate authorities to trust when fetching this feed.
+    pub ca_bundle: Option<String>,
+    /// A PEM file holding the certificate to identify with when fetching this feed.
+    pub client_cert: Option<String>,
+    /// A PEM file holding the PKCS #8 private key of `client_cert`.
+    pub client_key: Option<String>,
+    /// Shell commands through which the fetched feed is piped, in order, before it is checked and
+    /// cached. Each gets the feed on standard input and prints the fixed feed.
+    pub filters: Vec<String>,
+    /// The least time between updates of this feed, as a duration such as `6h` or `1d`.
+    pub interval: Option<String>,
+    /// The credentials to log in with when fetching this feed.
+    pub auth: Option<Auth>,
+}
+
+/// A rule hiding entries; see the `rules` module.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    /// The titles of the feeds the rule applies to.
+    pub feeds: Vec<String>,
+    /// The tags of the feeds the rule applies to.
+    pub tags: Vec<String>,
+    /// A regular expression matching the titles of entries to hide.
+    pub title: Option<String>,
+    /// A regular expression matching the contents of entries to hide.
+    pub content: Option<String>,
+    /// The age, such as `30d`, beyond which entries are hidden.
+    pub older_than: Option<String>,
+}
+
+/// How to authenticate to a feed. The secret, a password or token, is never written into the
+/// configuration itself but read from the first line of a file or of a command's output, so that
+/// it can be kept in a password manager.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Auth {
+    /// The HTTP authentication scheme.
+    pub scheme: AuthScheme,
+    /// The user to log in as, for basic authentication.
+    pub username: Option<String>,
+    /// A file holding the secret.
+    pub secret_file: Option<String>,
+    /// A shell command printing the secret, such as `pass show feeds/ci`.
+    pub secret_command: Option<String>,
+}
+
+/// The supported HTTP authentication schemes.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthScheme {
+    /// A username and password (RFC 7617).
+    Basic,
+    /// A bearer token (RFC 6750).
+    Bearer,
+}
+
+/// Credentials ready to be sent with a request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credentials {
+    /// A username and password.
+    Basic {
+        /// The user to log in as.
+        username: String,
+        /// Their password.
+        password: String,
+    },
+    /// A bearer token.
+    Bearer(String),
+}
+
+impl Auth {
+    /// Looks up the secret and returns the credentials to send.
+    pub fn credentials(&self) -> Result<Credentials, String> {
+        let secret = match (&self.secret_file, &self.secret_command) {
+            (Some(path), None) => fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?,
+            (None, Some(command)) => String::from_utf8_lossy(&command::run(command, None)?).into_owned(),
+            _ => return Err(String::from("auth needs exactly one of secret_file and secret_command")),
+        };
+        let secret = String::from(secret.lines().next().unwrap_or_default());
+        match (self.scheme, &self.username) {
+            (AuthScheme::Basic, Some(username)) => Ok(Credentials::Basic { username: username.clone(), password: secret }),
+            (AuthScheme::Basic, None) => Err(String::from("basic auth needs a username")),
+            (AuthScheme::Bearer, _) => Ok(Credentials::Bearer(secret)),
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.secret_file.is_some() == self.secret_command.is_some() {
+            return Err(String::from("auth needs exactly one of secret_file and secret_command"));
+        }
+        match (self.scheme, &self.username) {
+            (AuthScheme::Basic, None) => Err(String::from("basic auth needs a username")),
+            (AuthScheme::Bearer, Some(_)) => Err(String::from("bearer auth takes no username")),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FeedConfig {
+    /// Checks whether fetching this feed needs a connection set up differently from the global
+    /// one, that is through another proxy or with other TLS settings.
+    pub fn has_own_connection(&self) -> bool {
+        self.proxy.is_some() || self.ca_bundle.is_some() || self.client_cert.is_some()
+    }
+
+    /// The configured minimum interval between updates, if any.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval.as_deref().and_then(|interval| parse_duration(interval).ok())
+    }
+}
+
+impl Config {
+    /// Reads and validates the configuration file at `path`, falling back to the defaults if
+    /// there is none. Errors name the file and, where possible, the offending line.
+    pub fn load(path: &str) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|err| format!("{}: {}", path, err)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{}: {}", path, err)),
+        }
+    }
+
+    /// Parses and validates a configuration.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns the settings of the feed with the given title and `xmlUrl`. Settings keyed by
+    /// title take precedence over those keyed by URL.
+    pub fn feed(&self, title: &str, link_xml: &str) -> FeedConfig {
+        self.feeds
+            .get(title)
+            .or_else(|| self.feeds.get(link_xml))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.concurrency == 0 {
+            return Err(String::from("concurrency must be at least 1"));
+        }
+        if self.timeout == Some(0) {
+            return Err(String::from("timeout must be at least 1 second"));
+        }
+        if StrftimeItems::new(&self.date_format).any(|item| item == Item::Error) {
+            return Err(format!("invalid date_format: {}", self.date_format));
+        }
+        Rules::new(&self.rules, Utc::now())?;
+        validate_connection(&self.headers, &self.proxy, &self.client_cert, &self.client_key)?;
+        for (key, feed) in &self.feeds {
+            if feed.timeout == Some(0) {
+                return Err(format!("feeds.{}: timeout must be at least 1 second", key));
+            }
+            if let Some(interval) = &feed.interval {
+                parse_duration(interval).map_err(|err| format!("feeds.{}: {}", key, err))?;
+            }
+            if let Some(auth) = &feed.auth {
+                auth.validate().map_err(|err| format!("feeds.{}: {}", key, err))?;
+            }
+            validate_connection(&feed.headers, &feed.proxy, &feed.client_cert, &feed.client_key)
+                .map_err(|err| format!("feeds.{}: {}", key, err))?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the settings shared by `Config` and `FeedConfig` which decide how requests are sent.
+fn validate_connection(
+    headers: &BTreeMap<String, String>,
+    proxy: &Option<String>,
+    client_cert: &Option<String>,
+    client_key: &Option<String>,
+) -> Result<(), String> {
+    for (name, value) in headers {
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            return Err(format!("invalid header name: {}", name));
+        }
+        if value.chars().any(|c| c.is_control() && c != '\t') {
+            return Err(format!("invalid value for header {}", name));
+        }
+    }
+    if let Some(proxy) = proxy.as_deref().filter(|proxy| !proxy.is_empty()) {
+        reqwest::Proxy::all(proxy).map_err(|err| format!("invalid proxy {}: {}", proxy, err))?;
+    }
+    if client_cert.is_some() != client_key.is_some() {
+        return Err(String::from("client_cert and client_key must be given together"));
+    }
+    Ok(())
+}
+
+/// Checks whether a character may appear in an HTTP header name (RFC 7230).
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config_empty_is_default() {
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+    }
+
+    #[test]
+    fn config_feed_overrides() {
+        let config = Config::parse(r#"
+            timeout = 10
+            [feeds.npr]
+            disabled = true
+            [feeds."https://neovim.io/news.xml"]
+            headers = { X-Api-Key = "secret" }
+            filters = ["tidy -xml", "cat"]
+        "#).unwrap();
+        assert_eq!(config.timeout, Some(10));
+        assert!(config.feed("npr", "https://feeds.npr.org/1001/rss.xml").disabled);
+        let neovim = config.feed("neovim", "https://neovim.io/news.xml");
+        assert_eq!(neovim.headers.get("X-Api-Key").map(String::as_str), Some("secret"));
+        assert_eq!(neovim.filters, vec![String::from("tidy -xml"), String::from("cat")]);
+        assert_eq!(config.feed("slashdot", "http://rss.slashdot.org/"), FeedConfig::default());
+    }
+
+    #[test]
+    fn config_auth() {
+        let config = Config::parse(r#"
+            [feeds.ci.auth]
+            scheme = "basic"
+            username = "ty"
+            secret_command = "printf 'hunter2\\nsecond line'"
+        "#).unwrap();
+        let credentials = config.feed("ci", "").auth.unwrap().credentials();
+        assert_eq!(credentials, Ok(Credentials::Basic { username: String::from("ty"), password: String::from("hunter2") }));
+        let failing = Auth { scheme: AuthScheme::Bearer, username: None, secret_file: None, secret_command: Some(String::from("false")) };
+        assert!(failing.credentials().is_err());
+    }
+
+    #[test]
+    fn config_invalid() {
+        assert!(Config::parse("timeout = \"soon\"").is_err());
+        assert!(Config::parse("colour = true").is_err());
+        assert!(Config::parse("concurrency = 0").is_err());
+        assert!(Config::parse("date_format = \"%Q\"").is_err());
+        assert!(Config::parse("[[rules]]\ntitle = \"(\"").is_err());
+        assert!(Config::parse("proxy = \"not a url\"").is_err());
+        assert!(Config::parse("[feeds.npr]\nclient_cert = \"/tmp/cert.pem\"").is_err());
+        assert!(Config::parse("headers = { \"X Bad\" = \"x\" }").is_err());
+        assert!(Config::parse("[feeds.npr]\ninterval = \"often\"").is_err());
+        assert!(Config::parse("[feeds.npr]\ninterval = \"-1h\"").is_err());
+        assert!(Config::parse("[feeds.npr.auth]\nscheme = \"basic\"\nsecret_file = \"/tmp/secret\"").is_err());
+        assert!(Config::parse("[feeds.npr]\nheaders = { \"Bad Header\" = \"x\" }").is_err());
+    }
+}
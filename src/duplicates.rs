@@ -0,0 +1,125 @@
+//! Detection of the same entry carried by several feeds, such as a site's main feed and a feed of
+//! its top stories, or an aggregator reposting articles. Two entries are duplicates if they share
+//! an id, a link once tracking parameters are removed, or a title once case, punctuation and
+//! spacing are ignored.
+
+use crate::{normalize_url, Entry};
+use std::collections::HashMap;
+
+/// Query parameters which only tell the publisher where a reader came from.
+const TRACKING_PARAMETERS: [&str; 8] = ["dclid", "fbclid", "gclid", "igshid", "mc_cid", "mc_eid", "msclkid", "yclid"];
+
+/// Normalizes the link of an entry like `normalize_url()`, and also drops tracking parameters
+/// such as `utm_source` from its query.
+pub fn canonical_link(link: &str) -> String {
+    let link = link.trim();
+    let link = link.split('#').next().unwrap_or(link);
+    let (base, query) = link.split_once('?').unwrap_or((link, ""));
+    let query: Vec<&str> = query
+        .split('&')
+        .filter(|parameter| {
+            let name = parameter.split('=').next().unwrap_or(parameter).to_lowercase();
+            !name.is_empty() && !name.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&&name[..])
+        })
+        .collect();
+    let base = normalize_url(base);
+    if query.is_empty() {
+        base
+    } else {
+        format!("{}?{}", base, query.join("&"))
+    }
+}
+
+/// Lowercases a title and reduces it to its words, separated by single spaces.
+pub fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Groups duplicate entries, given along with the titles of their feeds. Returns, for each entry,
+/// the position of the first entry of its group, which is its own position if it duplicates no
+/// earlier one. Duplicates of duplicates belong to the same group. Entries of the same feed are
+/// never taken for duplicates of each other, since feeds repeat titles such as "Open Thread".
+pub fn groups<'a>(entries: impl IntoIterator<Item = (&'a str, &'a Entry)>) -> Vec<usize> {
+    let mut firsts: HashMap<String, (usize, &str)> = HashMap::new();
+    let mut parents: Vec<usize> = Vec::new();
+    for (position, (feed, entry)) in entries.into_iter().enumerate() {
+        parents.push(position);
+        let keys = [
+            entry.id.as_ref().map(|id| format!("id:{}", id.trim())),
+            entry.link.as_ref().map(|link| format!("link:{}", canonical_link(link))),
+            Some(format!("title:{}", normalize_title(&entry.title))).filter(|key| key.len() > "title:".len()),
+        ];
+        for key in keys.iter().flatten() {
+            match firsts.get(key) {
+                Some((first, first_feed)) if *first_feed != feed => {
+                    let (root, other) = (find(&mut parents, *first), find(&mut parents, position));
+                    parents[root.max(other)] = root.min(other);
+                },
+                Some(_) => {},
+                None => {
+                    firsts.insert(key.clone(), (position, feed));
+                },
+            }
+        }
+    }
+    (0..parents.len()).map(|position| find(&mut parents, position)).collect()
+}
+
+/// Finds the root of the group of `position`, shortening the path to it on the way.
+fn find(parents: &mut [usize], position: usize) -> usize {
+    let mut root = position;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut position = position;
+    while parents[position] != root {
+        let next = parents[position];
+        parents[position] = root;
+        position = next;
+    }
+    root
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(title: &str, id: Option<&str>, link: Option<&str>) -> Entry {
+        Entry {
+            title: String::from(title),
+            id: id.map(String::from),
+            link: link.map(String::from),
+            ..Entry::default()
+        }
+    }
+
+    #[test]
+    fn canonical_links() {
+        assert_eq!(
+            canonical_link("https://WWW.npr.org/2021/story/?utm_source=rss&id=7&fbclid=x#top"),
+            "www.npr.org/2021/story?id=7",
+        );
+        assert_eq!(canonical_link("http://npr.org/story?utm_medium=feed"), "npr.org/story");
+        assert_eq!(normalize_title("  Breaking:  Koi Spotted -- in Pond! "), "breaking koi spotted in pond");
+    }
+
+    #[test]
+    fn duplicates_grouped() {
+        let entries = [
+            ("npr", entry("Koi Spotted", Some("npr-1"), Some("https://npr.org/koi"))),
+            ("npr", entry("Open Thread", None, None)),
+            ("nprupfirst", entry("koi spotted!", Some("up-1"), None)),
+            ("aggregator", entry("Pond News", Some("agg-1"), Some("http://npr.org/koi?utm_source=agg"))),
+            ("aggregator", entry("Open Thread", None, None)),
+            ("npr", entry("Open Thread", None, None)),
+            ("aggregator", entry("Unrelated", Some("npr-1x"), None)),
+        ];
+        let groups = groups(entries.iter().map(|(feed, entry)| (*feed, entry)));
+        assert_eq!(groups, vec![0, 1, 0, 0, 1, 5, 6]);
+    }
+}
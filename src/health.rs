@@ -0,0 +1,118 @@
+//! Assessment of how well a subscription is doing, from its recorded state and cached entries,
+//! to help decide which feeds to prune.
+
+use crate::state::FeedState;
+use crate::Feed;
+use chrono::{DateTime, Duration, Utc};
+
+/// The number of consecutive failed updates after which a feed is considered dead.
+pub const FAILURES_DEAD: u32 = 5;
+
+/// How long a feed may go without a new entry before it is considered dead.
+pub const SILENCE_DEAD: Duration = Duration::days(180);
+
+/// The overall condition of a feed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    /// The feed has never been updated successfully nor failed to be.
+    Unknown,
+    /// The most recent update succeeded and the feed is still publishing.
+    Healthy,
+    /// The most recent update failed.
+    Failing,
+    /// The feed has failed `FAILURES_DEAD` times in a row or has been silent for
+    /// `SILENCE_DEAD`.
+    Dead,
+}
+
+impl Verdict {
+    /// A short lowercase name for the verdict.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Verdict::Unknown => "unknown",
+            Verdict::Healthy => "healthy",
+            Verdict::Failing => "failing",
+            Verdict::Dead => "dead",
+        }
+    }
+}
+
+/// What is known about the condition of a single feed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Health {
+    /// The verdict drawn from everything else.
+    pub verdict: Verdict,
+    /// The date of the newest entry in the cache.
+    pub newest: Option<DateTime<Utc>>,
+    /// The average number of entries published per week, over the time from the oldest cached
+    /// entry until now (but at least a week).
+    pub per_week: f64,
+}
+
+impl Health {
+    /// Assesses a feed from its state and its cached version, if there is one, at time `now`.
+    pub fn assess(state: &FeedState, feed: Option<&Feed>, now: DateTime<Utc>) -> Self {
+        let timestamps: Vec<DateTime<Utc>> = feed
+            .into_iter()
+            .flat_map(Feed::entries)
+            .filter_map(|entry| entry.timestamp())
+            .map(|timestamp| timestamp.with_timezone(&Utc))
+            .collect();
+        let newest = timestamps.iter().max().copied();
+        let per_week = match timestamps.iter().min() {
+            Some(oldest) => {
+                let weeks = (now - *oldest).num_seconds() as f64 / Duration::weeks(1).num_seconds() as f64;
+                timestamps.len() as f64 / weeks.max(1.0)
+            },
+            None => 0.0,
+        };
+        let silent = newest.is_some_and(|newest| now - newest > SILENCE_DEAD);
+        let verdict = if state.failures >= FAILURES_DEAD || silent {
+            Verdict::Dead
+        } else if state.failures > 0 {
+            Verdict::Failing
+        } else if state.updated.is_some() {
+            Verdict::Healthy
+        } else {
+            Verdict::Unknown
+        };
+        Self { verdict, newest, per_week }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::now;
+
+    fn feed(dates: &[&str]) -> Feed {
+        let items: String = dates
+            .iter()
+            .map(|date| format!("<item><pubDate>{}</pubDate></item>", date))
+            .collect();
+        Feed::new(format!("<rss><channel>{}</channel></rss>", items))
+    }
+
+    #[test]
+    fn health_healthy() {
+        let mut state = FeedState::default();
+        state.record_update(now());
+        let feed = feed(&["2021-08-09", "2021-08-06", "2021-07-27T12:00:00Z"]);
+        let health = Health::assess(&state, Some(&feed), now());
+        assert_eq!(health.verdict, Verdict::Healthy);
+        assert_eq!(health.newest, Some(now() - Duration::hours(36)));
+        assert!((health.per_week - 1.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn health_dead() {
+        let mut state = FeedState::default();
+        state.record_update(now());
+        assert_eq!(Health::assess(&state, Some(&feed(&["2020-01-01"])), now()).verdict, Verdict::Dead);
+        for _ in 0..FAILURES_DEAD {
+            state.record_failure(now(), "HTTP 404 Not Found");
+        }
+        assert_eq!(Health::assess(&state, None, now()).verdict, Verdict::Dead);
+        assert_eq!(Health::assess(&FeedState::default(), None, now()).verdict, Verdict::Unknown);
+    }
+}
@@ -0,0 +1,136 @@
+//! An on-disk inverted index of the words in entry titles and contents, kept per feed in an
+//! `.index` directory next to the cached feeds so that searches need not parse every cache.
+//!
+//! Each index file maps every lowercased alphanumeric word of a feed to the positions of the
+//! entries containing it, one word per line: `word<TAB>0,3,5`.
+
+use crate::search::Query;
+use crate::Feed;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, ErrorKind};
+
+/// The inverted index of a single feed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedIndex {
+    words: BTreeMap<String, BTreeSet<usize>>,
+}
+
+impl FeedIndex {
+    /// Builds the index of a feed from its entries.
+    pub fn new(feed: &Feed) -> Self {
+        let mut words: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+        for (index, entry) in feed.entries().enumerate() {
+            let text = format!("{}\n{}", entry.title, entry.content).to_lowercase();
+            for word in tokenize(&text) {
+                words.entry(String::from(word)).or_default().insert(index);
+            }
+        }
+        Self { words }
+    }
+
+    /// Reads the index of the feed titled `title` from the feed directory `dir_path`, returning
+    /// `None` if that feed has not been indexed.
+    pub fn load(dir_path: &str, title: &str) -> io::Result<Option<Self>> {
+        let text = match fs::read_to_string(Self::path(dir_path, title)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut words = BTreeMap::new();
+        for line in text.lines() {
+            let (word, indexes) = match line.split_once('\t') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let indexes = indexes
+                .split(',')
+                .filter_map(|index| index.parse().ok())
+                .collect();
+            words.insert(String::from(word), indexes);
+        }
+        Ok(Some(Self { words }))
+    }
+
+    /// Writes the index of the feed titled `title` into the feed directory `dir_path`.
+    pub fn save(&self, dir_path: &str, title: &str) -> io::Result<()> {
+        fs::create_dir_all(format!("{}/.index", dir_path))?;
+        let mut text = String::new();
+        for (word, indexes) in &self.words {
+            let indexes: Vec<String> = indexes.iter().map(usize::to_string).collect();
+            text.push_str(&format!("{}\t{}\n", word, indexes.join(",")));
+        }
+        fs::write(Self::path(dir_path, title), text)
+    }
+
+    /// Deletes every index in the feed directory `dir_path`.
+    pub fn clear(dir_path: &str) -> io::Result<()> {
+        match fs::remove_dir_all(format!("{}/.index", dir_path)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the positions of all entries which may match `query`, a superset of those which
+    /// do. Returns `None` when the index cannot narrow the search, as for regular expressions or
+    /// queries without any alphanumeric characters.
+    pub fn candidates(&self, query: &Query) -> Option<BTreeSet<usize>> {
+        let text = query.substring_text()?;
+        let mut candidates: Option<BTreeSet<usize>> = None;
+        for query_word in tokenize(text) {
+            // A substring may begin or end partway through a word of the entry.
+            let matching: BTreeSet<usize> = self.words
+                .iter()
+                .filter(|(word, _)| word.contains(query_word))
+                .flat_map(|(_, indexes)| indexes.iter().copied())
+                .collect();
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&matching).copied().collect(),
+                None => matching,
+            });
+        }
+        candidates
+    }
+
+    fn path(dir_path: &str, title: &str) -> String {
+        format!("{}/.index/{}", dir_path, title)
+    }
+}
+
+/// Splits already lowercased text into its alphanumeric words.
+fn tokenize(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search::Field;
+
+    static RSS: &str = r#"<?xml version="1.0"?>
+        <rss version="2.0"><channel>
+            <item><title>Rust 1.54 released</title><description>Incremental compilation</description></item>
+            <item><title>Cargo tips</title><description>Workspaces in rust</description></item>
+            <item><title>Unrelated</title><description>Gardening</description></item>
+        </channel></rss>
+    "#;
+
+    #[test]
+    fn candidates_superset() {
+        let index = FeedIndex::new(&Feed::new(RSS.to_string()));
+        let query = Query::substring("RUST", Field::All);
+        let expected: BTreeSet<usize> = vec![0, 1].into_iter().collect();
+        assert_eq!(index.candidates(&query), Some(expected));
+        let query = Query::substring("ust 1.5", Field::All);
+        let expected: BTreeSet<usize> = vec![0].into_iter().collect();
+        assert_eq!(index.candidates(&query), Some(expected));
+    }
+
+    #[test]
+    fn candidates_regex_unsupported() {
+        let index = FeedIndex::new(&Feed::new(RSS.to_string()));
+        let query = Query::regex("rust", Field::All).unwrap();
+        assert_eq!(index.candidates(&query), None);
+    }
+}
@@ -3,13 +3,46 @@
 //! This crate provides two simple newtypes over `String`s as well as a few convenience functions
 //! to ease the manipulation of newsfeeds in RSS 2.0 or Atom format.
 
+pub mod cache;
+pub mod changes;
+pub mod charset;
+pub mod command;
+pub mod config;
+pub mod duplicates;
+pub mod health;
+pub mod index;
+pub mod newsboat;
+pub mod paths;
+pub mod range;
+pub mod rules;
+pub mod schedule;
+pub mod search;
+pub mod state;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+use std::{error, fmt, mem};
+use xml::escape::escape_str_attribute;
 use xml::reader::{Error, EventReader, XmlEvent};
 
 const KEYS_CONTENT: [&str; 2] = ["content", "description"];
 const KEYS_DATE:    [&str; 4] = ["published", "updated", "pubDate", "date"];
 const KEYS_ENTRY:   [&str; 2] = ["entry", "item"];
+const KEYS_ROOT:    [&str; 3] = ["rss", "feed", "RDF"];
 const KEYS_TITLE:   [&str; 1] = ["title"];
 
+/// The number of hexadecimal digits in an entry reference.
+pub const REFERENCE_LENGTH: usize = 7;
+
+const OPML_EMPTY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<opml version="2.0">
+    <head>
+        <title>koifeed subscriptions</title>
+    </head>
+    <body>
+    </body>
+</opml>
+"#;
+
 /// A newtype struct to help manipulation of an OPML 2.0 subscription list.
 pub struct Opml(String);
 
@@ -28,6 +61,11 @@ impl Opml {
         }
     }
 
+    /// Returns a subscription list without any subscriptions.
+    pub fn empty() -> Self {
+        Self(String::from(OPML_EMPTY))
+    }
+
     /// Returns an iterator over attribute values for each `<outline>` element whose
     /// attribute name exactly matches that given. For simple cases such as the `text` or
     /// `xmlUrl` attributes, other convenience methods are provided.
@@ -93,16 +131,86 @@ impl Opml {
             })
     }
 
-    /// Find the first OPML entry whose "text" attribute is a non-strict superstring of the given
-    /// key; return the full value of that attribute if found.
-    pub fn find(&self, key: &str) -> Option<String> {
-        let titles = self.attribute_values("text");
-        for title in titles {
-            if title.contains(key) {
-                return Some(title);
+    /// Finds the OPML entry meant by a key and returns its "text" attribute. A title equal to the
+    /// key is preferred, followed by one equal to it apart from case, the only title starting
+    /// with the key, and the only title containing it; prefixes and substrings ignore case. When
+    /// several titles match at the first step that matches at all, the key is ambiguous.
+    pub fn find(&self, key: &str) -> Result<String, FindError> {
+        self.find_with(key, FindOptions::default())
+    }
+
+    /// Like `find()`, with further ways of matching enabled by `options`.
+    pub fn find_with(&self, key: &str, options: FindOptions) -> Result<String, FindError> {
+        let key_lower = key.to_lowercase();
+        let subscriptions: Vec<(String, String, Option<String>)> = self.titles()
+            .zip(self.links_xml())
+            .zip(self.links_html())
+            .map(|((title, link_xml), link_html)| (title, link_xml, link_html))
+            .collect();
+        let urls = |link_xml: &str, link_html: &Option<String>| {
+            let mut urls = vec![String::from(link_xml)];
+            urls.extend(link_html.clone());
+            urls
+        };
+        let pick = |matches: Vec<&String>| match matches.len() {
+            0 => None,
+            1 => Some(Ok(matches[0].clone())),
+            _ => Some(Err(FindError::Ambiguous(
+                String::from(key),
+                matches.into_iter().cloned().collect(),
+            ))),
+        };
+
+        let exact = subscriptions
+            .iter()
+            .filter(|(title, link_xml, link_html)| {
+                title == key || (options.urls && urls(link_xml, link_html).iter().any(|url| url == key))
+            })
+            .map(|(title, ..)| title);
+        if let Some(result) = pick(exact.collect()) {
+            return result;
+        }
+        let equal_ignoring_case = subscriptions
+            .iter()
+            .filter(|(title, ..)| title.to_lowercase() == key_lower)
+            .map(|(title, ..)| title);
+        if let Some(result) = pick(equal_ignoring_case.collect()) {
+            return result;
+        }
+        let prefixed = subscriptions
+            .iter()
+            .filter(|(title, ..)| title.to_lowercase().starts_with(&key_lower))
+            .map(|(title, ..)| title);
+        if let Some(result) = pick(prefixed.collect()) {
+            return result;
+        }
+        let containing = subscriptions
+            .iter()
+            .filter(|(title, link_xml, link_html)| {
+                title.to_lowercase().contains(&key_lower)
+                    || (options.urls && urls(link_xml, link_html).iter().any(|url| url.to_lowercase().contains(&key_lower)))
+            })
+            .map(|(title, ..)| title);
+        if let Some(result) = pick(containing.collect()) {
+            return result;
+        }
+        if options.fuzzy {
+            let scored: Vec<(usize, &String)> = subscriptions
+                .iter()
+                .filter_map(|(title, ..)| fuzzy_span(&title.to_lowercase(), &key_lower).map(|span| (span, title)))
+                .collect();
+            if let Some(best) = scored.iter().map(|(span, _)| *span).min() {
+                let matches = scored
+                    .into_iter()
+                    .filter(|(span, _)| *span == best)
+                    .map(|(_, title)| title)
+                    .collect();
+                if let Some(result) = pick(matches) {
+                    return result;
+                }
             }
         }
-        None
+        Err(FindError::NotFound(String::from(key)))
     }
 
     /// Convenience function returning an iterator over optional HTML links given in the OPML.
@@ -117,11 +225,155 @@ impl Opml {
         self.attribute_values("xmlUrl")
     }
 
+    /// Adds the subscriptions of another subscription list, such as an export from another
+    /// reader, to the end of this one. Subscriptions whose normalized feed URL (see
+    /// `normalize_url()`) is already present are skipped, as are those whose title is already taken
+    /// by a different feed, since titles name the cached feeds. Subscriptions inside folders of the
+    /// other list are tagged with the folder names if `folder_tags` is set.
+    ///
+    /// Subscriptions to local files (`file:`) and commands (`exec:`) are skipped unless `local`
+    /// is set, since a shared list could otherwise run arbitrary commands on every update.
+    pub fn merge(&mut self, other: &Opml, folder_tags: bool, local: bool) -> Result<MergeReport, String> {
+        self.merge_outlines(other.outlines(folder_tags), local)
+    }
+
+    /// Like `merge()`, for subscriptions from any source.
+    pub fn merge_outlines(&mut self, outlines: Vec<Outline>, local: bool) -> Result<MergeReport, String> {
+        let mut known: Vec<(String, String)> = self.titles()
+            .zip(self.links_xml())
+            .map(|(title, link_xml)| (title, normalize_url(&link_xml)))
+            .collect();
+        let mut report = MergeReport::default();
+        for outline in outlines {
+            let url = normalize_url(&outline.xml_url);
+            let scheme = outline.xml_url.trim_start().to_lowercase();
+            if !local && scheme.starts_with("exec:") {
+                report.skipped.push((outline, String::from("runs a command on every update; local sources are not allowed")));
+            } else if !local && scheme.starts_with("file:") {
+                report.skipped.push((outline, String::from("reads a local file; local sources are not allowed")));
+            } else if let Some((title, _)) = known.iter().find(|(_, known_url)| *known_url == url) {
+                let reason = format!("already subscribed as {}", title);
+                report.skipped.push((outline, reason));
+            } else if let Some((_, known_url)) = known.iter().find(|(title, _)| *title == outline.title) {
+                let reason = format!("title already used for {}", known_url);
+                report.conflicting.push((outline, reason));
+            } else {
+                known.push((outline.title.clone(), url));
+                report.added.push(outline);
+            }
+        }
+        if report.added.is_empty() {
+            return Ok(report);
+        }
+
+        let added: String = report.added
+            .iter()
+            .map(|outline| format!("        {}\n", outline.to_xml()))
+            .collect();
+        let text = if let Some(end) = self.0.rfind("</body>") {
+            let line_start = self.0[..end].rfind('\n').map_or(0, |newline| newline + 1);
+            let insert_at = if self.0[line_start..end].trim().is_empty() { line_start } else { end };
+            let mut text = self.0.clone();
+            text.insert_str(insert_at, &added);
+            text
+        } else if let Some(start) = self.0.find("<body/>").or_else(|| self.0.find("<body />")) {
+            let end = start + self.0[start..].find('>').unwrap() + 1;
+            format!("{}<body>\n{}    </body>{}", &self.0[..start], added, &self.0[end..])
+        } else {
+            return Err(String::from("subscription list has no <body> element"));
+        };
+        *self = Opml::new(text).map_err(|err| err.to_string())?;
+        Ok(report)
+    }
+
+    /// Points every subscription whose feed URL is `old` at `new` instead, such as after the
+    /// feed has moved. Returns whether any subscription was changed.
+    pub fn set_link_xml(&mut self, old: &str, new: &str) -> Result<bool, String> {
+        if !self.links_xml().any(|link_xml| link_xml == old) {
+            return Ok(false);
+        }
+        let (old, new) = (escape_str_attribute(old), escape_str_attribute(new));
+        let mut text = self.0.clone();
+        for quote in ['"', '\''].iter() {
+            text = text.replace(
+                &format!("xmlUrl={}{}{}", quote, old, quote),
+                &format!("xmlUrl={}{}{}", quote, new, quote),
+            );
+        }
+        if text == self.0 {
+            return Err(format!("cannot locate xmlUrl=\"{}\" in the subscription list", old));
+        }
+        *self = Opml::new(text).map_err(|err| err.to_string())?;
+        Ok(true)
+    }
+
+    /// Returns all subscriptions, that is all `<outline>` elements with an "xmlUrl" attribute,
+    /// regardless of how deeply they are nested in folders. Titles are taken from the "text"
+    /// attribute, or from "title" for lists which only give that. If `folder_tags` is set, the
+    /// titles of all enclosing folders are added to the tags of each subscription.
+    pub fn outlines(&self, folder_tags: bool) -> Vec<Outline> {
+        let parser = EventReader::new(self.0.as_bytes());
+        let mut folders: Vec<Option<String>> = Vec::new();
+        let mut outlines = Vec::new();
+        for event in parser.into_iter().filter_map(Result::ok) {
+            match event {
+                XmlEvent::StartElement { name, attributes, .. } if name.local_name == "outline" => {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|attribute| attribute.name.local_name == key)
+                            .map(|attribute| attribute.value.trim().to_string())
+                            .filter(|value| !value.is_empty())
+                    };
+                    let title = attribute("text").or_else(|| attribute("title"));
+                    let xml_url = match attribute("xmlUrl") {
+                        Some(xml_url) => xml_url,
+                        None => {
+                            folders.push(title);
+                            continue;
+                        },
+                    };
+                    folders.push(None);
+                    let mut tags: Vec<String> = attribute("category")
+                        .map(|category| {
+                            category
+                                .split(',')
+                                .map(|tag| tag.trim().trim_matches('/').to_string())
+                                .filter(|tag| !tag.is_empty())
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    if folder_tags {
+                        for folder in folders.iter().flatten() {
+                            if !tags.contains(folder) {
+                                tags.push(folder.clone());
+                            }
+                        }
+                    }
+                    // Titles name the cached feeds, so they must be usable as file names.
+                    let title = title
+                        .unwrap_or_else(|| title_from_url(&xml_url))
+                        .replace('/', "-");
+                    outlines.push(Outline {
+                        title,
+                        xml_url,
+                        html_url: attribute("htmlUrl"),
+                        tags,
+                    });
+                },
+                XmlEvent::EndElement { name } if name.local_name == "outline" => {
+                    folders.pop();
+                },
+                _ => {},
+            }
+        }
+        outlines
+    }
+
     /// Convenience function returning an iterator over all tag lists of entries in the
     /// OPML.
     pub fn tags(&self) -> impl Iterator<Item = Vec<String>> + '_ {
         self.attribute_values_optional("category")
-            .into_iter()
             .map(|tag_opt| {
                 match tag_opt {
                     Some(tag_string) => {
@@ -144,6 +396,130 @@ impl Opml {
     }
 }
 
+/// A single subscription of an `Opml`, as returned by `Opml::outlines()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Outline {
+    /// The title of the feed, which names its cache.
+    pub title: String,
+    /// The URL of the feed itself.
+    pub xml_url: String,
+    /// The URL of the homepage of the feed.
+    pub html_url: Option<String>,
+    /// The tags of the feed.
+    pub tags: Vec<String>,
+}
+
+impl Outline {
+    /// Renders the subscription as an `<outline>` element.
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from("<outline");
+        if !self.tags.is_empty() {
+            xml.push_str(&format!(" category=\"{}\"", escape_str_attribute(&self.tags.join(","))));
+        }
+        xml.push_str(&format!(" text=\"{}\" type=\"rss\"", escape_str_attribute(&self.title)));
+        if let Some(html_url) = &self.html_url {
+            xml.push_str(&format!(" htmlUrl=\"{}\"", escape_str_attribute(html_url)));
+        }
+        xml.push_str(&format!(" xmlUrl=\"{}\"/>", escape_str_attribute(&self.xml_url)));
+        xml
+    }
+}
+
+/// The outcome of `Opml::merge()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MergeReport {
+    /// Subscriptions which were added.
+    pub added: Vec<Outline>,
+    /// Subscriptions which were already present, with the reason they were skipped.
+    pub skipped: Vec<(Outline, String)>,
+    /// Subscriptions which could not be added because they clash with an existing one, with
+    /// the reason.
+    pub conflicting: Vec<(Outline, String)>,
+}
+
+/// Normalizes a feed URL for comparison, so that the same feed exported by different readers is
+/// recognized: the scheme is dropped (treating http and https alike), the host is lowercased and
+/// default ports, fragments and trailing slashes are removed.
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let url = url.split('#').next().unwrap_or(url);
+    let rest = match url.find("://") {
+        Some(separator) => &url[separator + 3..],
+        None => url,
+    };
+    let (host, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, ""),
+    };
+    let host = host.to_lowercase();
+    let host = host
+        .strip_suffix(":80")
+        .or_else(|| host.strip_suffix(":443"))
+        .unwrap_or(&host);
+    format!("{}{}", host, path.trim_end_matches('/'))
+}
+
+/// Names a feed which comes without a title after its normalized URL, which keeps feeds from
+/// the same host apart.
+pub(crate) fn title_from_url(url: &str) -> String {
+    normalize_url(url).replace('/', "-")
+}
+
+/// Further ways for `Opml::find_with()` to match a key against subscriptions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FindOptions {
+    /// Also match the key against the "xmlUrl" and "htmlUrl" attributes.
+    pub urls: bool,
+    /// As a last resort, match titles containing all characters of the key in order, preferring
+    /// those in which they lie closest together.
+    pub fuzzy: bool,
+}
+
+/// The ways in which `Opml::find()` can fail.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FindError {
+    /// No entry matches the key.
+    NotFound(String),
+    /// Several entries match the key equally well; all of their titles are given.
+    Ambiguous(String, Vec<String>),
+}
+
+impl fmt::Display for FindError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FindError::NotFound(key) => write!(formatter, "no feed matches \"{}\"", key),
+            FindError::Ambiguous(key, titles) => {
+                write!(formatter, "\"{}\" matches several feeds: {}", key, titles.join(", "))
+            },
+        }
+    }
+}
+
+impl error::Error for FindError {}
+
+/// Returns the length of the shortest stretch of `text` containing all characters of `key` in
+/// order, if there is one.
+fn fuzzy_span(text: &str, key: &str) -> Option<usize> {
+    let text: Vec<char> = text.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+    let first = *key.first()?;
+    (0..text.len())
+        .filter(|&start| text[start] == first)
+        .filter_map(|start| {
+            let mut remaining = key.iter().peekable();
+            for (offset, character) in text[start..].iter().enumerate() {
+                if remaining.peek() == Some(&character) {
+                    remaining.next();
+                    if remaining.peek().is_none() {
+                        return Some(offset + 1);
+                    }
+                }
+            }
+            None
+        })
+        .min()
+}
+
 /// A newtype struct to help manipulation of an RSS 2.0 or Atom feeds.
 pub struct Feed(String);
 
@@ -169,6 +545,29 @@ impl Feed {
         }
     }
 
+    /// Like `new_check_xml()`, but also checks that the XML is an RSS, Atom or RDF feed rather
+    /// than, say, an error page, by looking at its root element.
+    pub fn new_check_format(text: String) -> Result<Self, String> {
+        let mut root = None;
+        for event in EventReader::new(text.as_bytes()) {
+            match event {
+                Ok(XmlEvent::StartElement { name, .. }) if root.is_none() => {
+                    if !KEYS_ROOT.contains(&&name.local_name[..]) {
+                        return Err(format!("not a feed: root element is <{}>", name.local_name));
+                    }
+                    root = Some(name.local_name);
+                },
+                Err(err) if root.is_none() => return Err(format!("not a feed: {}", err)),
+                Err(err) => return Err(format!("malformed feed: {}", err)),
+                _ => {},
+            }
+        }
+        match root {
+            Some(_) => Ok(Self(text)),
+            None => Err(String::from("not a feed: no root element")),
+        }
+    }
+
     /* SECTION: methods */
 
     /// Convenience function returning an iterator over the main contents/descriptions of all
@@ -215,10 +614,7 @@ impl Feed {
                     _ => Some(None),
                 }
             })
-            .filter_map(|option| match option {
-                Some(string) => Some(string),
-                _ => None,
-            })
+            .flatten()
     }
 
     /// Returns an iterator over all enclosure URL's from this feed.
@@ -243,6 +639,35 @@ impl Feed {
             .map(|attribute| attribute.value)
     }
 
+    /// Looks up a single entry along with its position, either by its reference as given by
+    /// `Entry::reference()` or by its position in the feed, starting at zero. References take
+    /// precedence over positions.
+    pub fn entry(&self, key: &str) -> Option<(usize, Entry)> {
+        let position_wanted = key.parse::<usize>().ok();
+        let mut by_position = None;
+        for (position, entry) in self.entries().enumerate() {
+            if entry.reference() == key {
+                return Some((position, entry));
+            }
+            if position_wanted == Some(position) {
+                by_position = Some((position, entry));
+            }
+        }
+        by_position
+    }
+
+    /// Returns an iterator over all entries in the feed, each gathering the title, date, content,
+    /// id, link and enclosure of a single `<entry>` or `<item>`. Unlike zipping the other
+    /// convenience iterators together, this keeps fields aligned when some entries lack one.
+    pub fn entries(&self) -> impl Iterator<Item = Entry> + '_ {
+        let parser = EventReader::new(self.0.as_bytes());
+        let mut entry_parser = EntryParser::default();
+        parser
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(move |event| entry_parser.push(event))
+    }
+
     /// Convenience function returning an iterator over all entry links from the feed. Note that
     /// this method ignores enclosures.
     pub fn links(&self) -> impl Iterator<Item = String> + '_ {
@@ -280,10 +705,7 @@ impl Feed {
                     _ => Some(None),
                 }
             })
-            .filter_map(|option| match option {
-                Some(string) => Some(string),
-                _ => None,
-            })
+            .flatten()
     }
 
     /// Convenience function returning an iterator of all entry titles in the feed.
@@ -295,6 +717,207 @@ impl Feed {
     pub fn text(&self) -> &str { &self.0 }
 }
 
+/// A single entry of a feed, as returned by `Feed::entries()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entry {
+    /// The title of the entry, or an empty string if it has none.
+    pub title: String,
+    /// The date of publication or last update, exactly as given in the feed.
+    pub date: Option<String>,
+    /// The main content or description of the entry, or an empty string if it has none.
+    pub content: String,
+    /// The `<guid>` (RSS) or `<id>` (Atom) of the entry.
+    pub id: Option<String>,
+    /// The link to the entry itself.
+    pub link: Option<String>,
+    /// The URL of the first enclosure of the entry.
+    pub enclosure: Option<String>,
+}
+
+impl Entry {
+    /// Returns the date of the entry in ISO-8601 yyyy-mm-dd format. Dates which cannot be parsed
+    /// are returned as given, and missing dates as an empty string.
+    pub fn date_iso(&self) -> String {
+        self.date_formatted("%Y-%m-%d")
+    }
+
+    /// Like `date_iso()`, but formats the date according to a `strftime`-style format string.
+    pub fn date_formatted(&self, format: &str) -> String {
+        match (self.timestamp(), &self.date) {
+            (Some(timestamp), _) => timestamp.format(format).to_string(),
+            (None, Some(date)) => date.clone(),
+            (None, None) => String::new(),
+        }
+    }
+
+    /// Returns a short reference to the entry which, unlike its position, stays the same as the
+    /// feed changes. It is derived from the id of the entry, or from its link, title and date
+    /// for feeds which give no ids.
+    pub fn reference(&self) -> String {
+        let hash = match (&self.id, &self.link) {
+            (Some(id), _) => hash(id.as_bytes()),
+            (None, Some(link)) => hash(link.as_bytes()),
+            (None, None) => {
+                let date = self.date.as_deref().unwrap_or("");
+                hash(format!("{}\n{}", self.title, date).as_bytes())
+            },
+        };
+        let hex = format!("{:016x}", hash);
+        String::from(&hex[..REFERENCE_LENGTH])
+    }
+
+    /// Returns a hash of everything the entry holds, which changes whenever the publisher edits
+    /// it, unlike its reference.
+    pub fn digest(&self) -> String {
+        let text = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            self.title,
+            self.date.as_deref().unwrap_or(""),
+            self.id.as_deref().unwrap_or(""),
+            self.link.as_deref().unwrap_or(""),
+            self.enclosure.as_deref().unwrap_or(""),
+            self.content,
+        );
+        format!("{:016x}", hash(text.as_bytes()))
+    }
+
+    /// Parses the date of the entry, if it has one in a recognized format.
+    pub fn timestamp(&self) -> Option<DateTime<FixedOffset>> {
+        self.date.as_deref().and_then(date_parse_timestamp)
+    }
+
+    /// Fills in a field from element text, keeping the first non-empty value found.
+    fn set(&mut self, field: EntryField, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let text = String::from(text);
+        match field {
+            EntryField::Content if self.content.is_empty() => self.content = text,
+            EntryField::Title if self.title.is_empty() => self.title = text,
+            EntryField::Date => { self.date.get_or_insert(text); },
+            EntryField::Id => { self.id.get_or_insert(text); },
+            EntryField::Link => { self.link.get_or_insert(text); },
+            _ => {},
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EntryField {
+    Content,
+    Date,
+    Id,
+    Link,
+    Title,
+}
+
+/// Incremental state for `Feed::entries()`, fed one XML event at a time.
+#[derive(Default)]
+struct EntryParser {
+    depth: usize,
+    entry: Option<(usize, Entry)>,
+    field: Option<(usize, EntryField)>,
+    text: String,
+}
+
+impl EntryParser {
+    fn push(&mut self, event: XmlEvent) -> Option<Entry> {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let depth = self.depth;
+                self.depth += 1;
+                let name = &name.local_name[..];
+                let entry = match &mut self.entry {
+                    Some((_, entry)) => entry,
+                    None => {
+                        if KEYS_ENTRY.contains(&name) {
+                            self.entry = Some((depth, Entry::default()));
+                        }
+                        return None;
+                    },
+                };
+                if self.field.is_some() {
+                    return None;
+                }
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name == key)
+                        .map(|attribute| attribute.value.clone())
+                };
+                let field = match name {
+                    "enclosure" => {
+                        if entry.enclosure.is_none() {
+                            entry.enclosure = attribute("url");
+                        }
+                        None
+                    },
+                    "link" => match (attribute("href"), attribute("rel").as_deref()) {
+                        (Some(href), Some("enclosure")) => {
+                            entry.enclosure.get_or_insert(href);
+                            None
+                        },
+                        (Some(href), None) | (Some(href), Some("alternate")) => {
+                            entry.link.get_or_insert(href);
+                            None
+                        },
+                        (Some(_), Some(_)) => None,
+                        (None, _) => Some(EntryField::Link),
+                    },
+                    "guid" | "id" => Some(EntryField::Id),
+                    _ if KEYS_CONTENT.contains(&name) => Some(EntryField::Content),
+                    _ if KEYS_DATE.contains(&name) => Some(EntryField::Date),
+                    _ if KEYS_TITLE.contains(&name) => Some(EntryField::Title),
+                    _ => None,
+                };
+                self.field = field.map(|field| (depth, field));
+                None
+            },
+            XmlEvent::Characters(string) | XmlEvent::CData(string) | XmlEvent::Whitespace(string) => {
+                if self.field.is_some() {
+                    self.text.push_str(&string);
+                }
+                None
+            },
+            XmlEvent::EndElement { .. } => {
+                self.depth = self.depth.saturating_sub(1);
+                if let Some((depth, field)) = self.field {
+                    if depth == self.depth {
+                        self.field = None;
+                        let text = mem::take(&mut self.text);
+                        if let Some((_, entry)) = &mut self.entry {
+                            entry.set(field, text.trim());
+                        }
+                    }
+                }
+                match self.entry {
+                    Some((depth, _)) if depth == self.depth => {
+                        self.entry.take().map(|(_, entry)| entry)
+                    },
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+}
+
+/// 64-bit FNV-1a followed by the MurmurHash3 finalizer, so that similar ids such as sequential
+/// numbers differ in their leading digits too. Unlike the hasher of the standard library, this is
+/// guaranteed to stay the same across Rust releases, as anything written to disk or printed for
+/// scripts must.
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
 /// Naively but (probably) correctly converts the RFC 822 date format into ISO-8601.
 pub fn date_parse(date: &str) -> String {
     if date.contains(',') {
@@ -322,6 +945,52 @@ pub fn date_parse(date: &str) -> String {
     }
 }
 
+/// Parses a date given in RFC 3339 (Atom), RFC 822 (RSS) or bare yyyy-mm-dd format into a
+/// timestamp. Dates without an offset are assumed to be in UTC. RFC 822 dates whose day of the
+/// week disagrees with the rest of the date, which are common in the wild, are still accepted.
+pub fn date_parse_timestamp(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(date) {
+        return Some(timestamp);
+    }
+    if let Ok(timestamp) = DateTime::parse_from_rfc2822(date) {
+        return Some(timestamp);
+    }
+    if let Some((_, without_weekday)) = date.split_once(", ") {
+        if let Ok(timestamp) = DateTime::parse_from_rfc2822(without_weekday) {
+            return Some(timestamp);
+        }
+    }
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .and_then(|day| day.and_hms_opt(0, 0, 0))
+        })
+        .map(|naive| naive.and_utc().fixed_offset())
+}
+
+/// Fixtures shared by the tests of several modules.
+#[cfg(test)]
+pub(crate) mod testing {
+    use chrono::{DateTime, Utc};
+    use std::{env, fs, process};
+
+    /// The fixed time at which tests run.
+    pub fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2021-08-10T12:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    /// Returns the path of an empty temporary directory for the test called `name`, removing
+    /// whatever an earlier, interrupted run left there.
+    pub fn temp_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("koifeed-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        String::from(dir.to_str().unwrap())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -347,9 +1016,23 @@ mod test {
         assert_eq!(rfc_attempt, rfc_good);
     }
 
+    #[test]
+    fn timestamp_from_rfc_wrong_weekday() {
+        let actual   = date_parse_timestamp("Mon, 19 May 2002 15:21:36 GMT");
+        let expected = DateTime::parse_from_rfc3339("2002-05-19T15:21:36Z").ok();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn timestamp_from_bare_date() {
+        let actual   = date_parse_timestamp("2021-08-06");
+        let expected = DateTime::parse_from_rfc3339("2021-08-06T00:00:00Z").ok();
+        assert_eq!(actual, expected);
+    }
+
     /* SECTION: OPML */
 
-    static opml: &str = r#"
+    static OPML: &str = r#"
         <?xml version="1.0" encoding="utf-8"?> <opml version="2.0">
             <head />
             <body>
@@ -421,7 +1104,7 @@ mod test {
 
     #[test]
     fn opml_attribute_values() {
-        let opml_struct = Opml::new(opml.to_string()).unwrap();
+        let opml_struct = Opml::new(OPML.trim().to_string()).unwrap();
         let actual: Vec<String> = opml_struct
             .attribute_values("text")
             .collect();
@@ -439,9 +1122,100 @@ mod test {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn opml_find_exact_before_substring() {
+        let opml_struct = Opml::new(OPML.trim().to_string()).unwrap();
+        assert_eq!(opml_struct.find("npr"), Ok(String::from("npr")));
+        assert_eq!(opml_struct.find("NPR"), Ok(String::from("npr")));
+        assert_eq!(opml_struct.find("nprup"), Ok(String::from("nprupfirst")));
+        assert_eq!(opml_struct.find("Kurz"), Ok(String::from("kurzgesagt")));
+    }
+
+    #[test]
+    fn opml_find_ambiguous() {
+        let opml_struct = Opml::new(OPML.trim().to_string()).unwrap();
+        let expected = FindError::Ambiguous(
+            String::from("np"),
+            vec![String::from("npr"), String::from("nprupfirst")],
+        );
+        assert_eq!(opml_struct.find("np"), Err(expected));
+        assert_eq!(opml_struct.find("nothing"), Err(FindError::NotFound(String::from("nothing"))));
+    }
+
+    #[test]
+    fn opml_find_with_options() {
+        let opml_struct = Opml::new(OPML.trim().to_string()).unwrap();
+        let options = FindOptions { urls: true, fuzzy: true };
+        assert_eq!(opml_struct.find_with("anchor.fm", options), Ok(String::from("buildingwithrust")));
+        assert_eq!(opml_struct.find_with("kzgst", options), Ok(String::from("kurzgesagt")));
+        assert!(opml_struct.find("kzgst").is_err());
+    }
+
+    static OPML_EXPORT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+        <opml version="1.0">
+            <body>
+                <outline text="Tech" title="Tech">
+                    <outline title="Neovim" xmlUrl="http://neovim.io/news.xml/"/>
+                    <outline title="Rust Blog" xmlUrl="https://blog.rust-lang.org/feed.xml" category="/lang"/>
+                </outline>
+                <outline text="npr" title="NPR News" xmlUrl="https://feeds.npr.org/9999/rss.xml"/>
+            </body>
+        </opml>
+    "#;
+
+    #[test]
+    fn opml_outlines_from_folders() {
+        let export = Opml::new(OPML_EXPORT.to_string()).unwrap();
+        let outlines = export.outlines(true);
+        assert_eq!(outlines.len(), 3);
+        assert_eq!(outlines[1].title, "Rust Blog");
+        assert_eq!(outlines[1].tags, vec![String::from("lang"), String::from("Tech")]);
+        assert_eq!(outlines[2].title, "npr");
+        assert!(outlines[2].tags.is_empty());
+    }
+
+    #[test]
+    fn opml_merge() {
+        let mut opml_struct = Opml::new(OPML.trim().to_string()).unwrap();
+        let export = Opml::new(OPML_EXPORT.to_string()).unwrap();
+        let report = opml_struct.merge(&export, false, false).unwrap();
+        let added: Vec<&str> = report.added.iter().map(|outline| &outline.title[..]).collect();
+        assert_eq!(added, vec!["Rust Blog"]);
+        assert_eq!(report.skipped[0].0.title, "Neovim");
+        assert_eq!(report.conflicting[0].0.title, "npr");
+        assert_eq!(opml_struct.titles().last(), Some(String::from("Rust Blog")));
+        assert_eq!(opml_struct.find("Rust"), Ok(String::from("Rust Blog")));
+
+        let local = Opml::new(String::from(r#"<opml version="1.0"><body>
+            <outline text="scraped" xmlUrl="exec:touch /tmp/pwned"/>
+            <outline text="saved" xmlUrl="FILE:///tmp/feed.xml"/>
+        </body></opml>"#)).unwrap();
+        let report = opml_struct.merge(&local, false, false).unwrap();
+        assert!(report.added.is_empty());
+        let skipped: Vec<&str> = report.skipped.iter().map(|(outline, _)| &outline.title[..]).collect();
+        assert_eq!(skipped, vec!["scraped", "saved"]);
+        assert!(!opml_struct.links_xml().any(|link| link.starts_with("exec:")));
+        assert_eq!(opml_struct.merge(&local, false, true).unwrap().added.len(), 2);
+    }
+
+    #[test]
+    fn opml_set_link_xml() {
+        let mut opml_struct = Opml::new(OPML.trim().to_string()).unwrap();
+        let moved = "https://archlinux.org/feeds/news/?a=1&b=2";
+        assert_eq!(opml_struct.set_link_xml("https://archlinux.org/feeds/news/", moved), Ok(true));
+        assert_eq!(opml_struct.links_xml().next(), Some(String::from(moved)));
+        assert_eq!(opml_struct.set_link_xml("https://example.com/", moved), Ok(false));
+    }
+
+    #[test]
+    fn url_normalized() {
+        assert_eq!(normalize_url("HTTPS://Neovim.io:443/news.xml/#top"), "neovim.io/news.xml");
+        assert_eq!(normalize_url("http://neovim.io/news.xml"), "neovim.io/news.xml");
+    }
+
     /* SECTION: Feed */
 
-    static atom: &str = r#"
+    static ATOM: &str = r#"
         <?xml version="1.0" encoding="utf-8"?>
         <feed xmlns="http://www.w3.org/2005/Atom">
 
@@ -476,7 +1250,7 @@ mod test {
     /*
     #[test]
     fn rss_element_contents() {
-        let feed = Feed::new(rss.to_string()).unwrap();
+        let feed = Feed::new(RSS.trim().to_string()).unwrap();
         let actual = feed.element_contents(&KEYS_DATE);
         let expected = vec![
         ];
@@ -486,7 +1260,7 @@ mod test {
 
     #[test]
     fn atom_element_contents() {
-        let feed = Feed::new(atom.to_string()).unwrap();
+        let feed = Feed::new(ATOM.trim().to_string());
         let actual: Vec<String> = feed
             .element_contents(&KEYS_DATE)
             .collect();
@@ -496,4 +1270,36 @@ mod test {
         ];
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn atom_entries() {
+        let feed = Feed::new(ATOM.trim().to_string());
+        let entries: Vec<Entry> = feed.entries().collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Atom-Powered Robots Run Amok");
+        assert_eq!(entries[0].link.as_deref(), Some("http://example.org/2003/12/13/atom03"));
+        assert_eq!(entries[1].id.as_deref(), Some("http://tykozic.net/posts/rss-part-1"));
+        assert_eq!(entries[1].date_iso(), "2021-08-06");
+    }
+
+    #[test]
+    fn feed_format_checked() {
+        assert!(Feed::new_check_format(ATOM.trim().to_string()).is_ok());
+        let html = String::from("<!DOCTYPE html><html><body>Not Found</body></html>");
+        assert!(Feed::new_check_format(html).is_err());
+        let portal = String::from("<html><head><title>Log in</title></head></html>");
+        assert_eq!(Feed::new_check_format(portal).err(), Some(String::from("not a feed: root element is <html>")));
+        assert!(Feed::new_check_format(String::from("<rss><channel>")).is_err());
+    }
+
+    #[test]
+    fn atom_entry_by_reference() {
+        let feed = Feed::new(ATOM.trim().to_string());
+        let second = feed.entries().nth(1).unwrap();
+        let reference = second.reference();
+        assert_eq!(reference.len(), REFERENCE_LENGTH);
+        assert_eq!(feed.entry(&reference), Some((1, second.clone())));
+        assert_eq!(feed.entry("1"), Some((1, second)));
+        assert_eq!(feed.entry("2"), None);
+    }
 }
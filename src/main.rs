@@ -1,29 +1,136 @@
-use clap::{Arg, App, AppSettings, SubCommand};
-use koifeed::{Feed, Opml};
-use reqwest::blocking::Client;
-use std::env;
+use chrono::{DateTime, Local, Utc};
+use clap::{Arg, App, AppSettings, ArgMatches, SubCommand};
+use koifeed::{Entry, Feed, FindOptions, MergeReport, Opml};
+use koifeed::{cache, charset, command};
+use koifeed::duplicates;
+use koifeed::changes::{ChangeKind, FeedChanges};
+use koifeed::config::{Auth, Config, Credentials, FeedConfig, OutputFormat};
+use koifeed::health::{self, Health};
+use koifeed::index::FeedIndex;
+use koifeed::newsboat;
+use koifeed::paths::Paths;
+use koifeed::range::{DateBound, DateRange};
+use koifeed::rules::Rules;
+use koifeed::schedule;
+use koifeed::search::{Field, Query};
+use koifeed::state::FeedState;
+use reqwest::blocking::{Client, Response};
+use reqwest::{Certificate, Identity, Proxy, StatusCode, Url};
+use reqwest::header::{CACHE_CONTROL, CONTENT_TYPE, EXPIRES, LOCATION, USER_AGENT};
+use reqwest::redirect::Policy;
 use std::error::Error;
 use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
 
-fn main() -> Result<(), Box<dyn Error>> {
+/// The number of redirects `update` follows before giving up on a feed.
+const MAX_REDIRECTS: usize = 10;
+
+fn main() {
+    if let Err(err) = run() {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
     let arguments = App::new("koifeed")
         .about("Composable CLI for RSS/Atom feeds, written in Rust")
         .setting(AppSettings::SubcommandRequired)
+        .arg(Arg::with_name("fuzzy")
+             .help("Let feed keys match titles containing their characters in order, when nothing else matches")
+             .long("fuzzy")
+             .global(true))
+        .arg(Arg::with_name("match-urls")
+             .help("Let feed keys match the feed and homepage URLs of each feed as well as titles")
+             .long("match-urls")
+             .global(true))
+        .arg(Arg::with_name("no-filters")
+             .help("Show the entries which the rules in the configuration would hide")
+             .long("no-filters")
+             .global(true))
+        .arg(Arg::with_name("opml")
+             .help("The OPML subscription list to use instead of feeds.opml in the configuration directory (also KOIFEED_OPML)")
+             .long("opml")
+             .takes_value(true)
+             .global(true))
+        .arg(Arg::with_name("data-dir")
+             .help("The directory of cached feeds to use instead of koifeed in $XDG_DATA_HOME (also KOIFEED_DATA_DIR)")
+             .long("data-dir")
+             .takes_value(true)
+             .global(true))
+        .arg(Arg::with_name("output-format")
+             .help("How to print listings: tab-separated tables with headers, or bare tab-separated values for other programs")
+             .long("output-format")
+             .takes_value(true)
+             .possible_values(&["table", "tsv"])
+             .global(true))
         .subcommand(SubCommand::with_name("content")
             .about("Get the main content of an entry")
             .arg(Arg::with_name("feed")
                  .help("A key by which to search for a feed title")
                  .required(true))
             .arg(Arg::with_name("entry")
-                 .help("An integer indexing the desired entry, starting at zero")
+                 .help("The reference of the desired entry as printed by list, or an integer indexing it, starting at zero")
                  .required(true)))
+        .subcommand(SubCommand::with_name("init")
+            .about("Create the configuration and data directories along with an empty or imported subscription list")
+            .arg(Arg::with_name("import")
+                 .help("An OPML file, such as an export from another reader, to start from")
+                 .long("import")
+                 .takes_value(true))
+            .arg(Arg::with_name("tag-from-folder")
+                 .help("Tag imported feeds with the names of the folders containing them")
+                 .long("tag-from-folder")
+                 .requires("import"))
+            .arg(arg_allow_local()
+                 .requires("import"))
+            .arg(Arg::with_name("force")
+                 .help("Replace an existing subscription list")
+                 .long("force")))
+        .subcommand(SubCommand::with_name("diff")
+            .about("List the entries which the latest update of each feed added, removed or modified")
+            .arg(Arg::with_name("feed")
+                 .help("A key by which to search for a feed title"))
+            .arg(Arg::with_name("tags")
+                 .help("A single, comma-separated argument restricting the listing to feeds with any of the specified tags")
+                 .short("t")
+                 .long("tags")
+                 .conflicts_with("feed")
+                 .value_delimiter(","))
+            .arg(arg_since())
+            .arg(arg_until()))
+        .subcommand(SubCommand::with_name("export")
+            .about("Print the subscription list as OPML or as a Newsboat urls file")
+            .arg(arg_subscription_format()))
+        .subcommand(SubCommand::with_name("health")
+            .about("Report how each feed has fared: last successful update, failures, the newest entry, and whether it looks dead")
+            .arg(Arg::with_name("tags")
+                 .help("A single, comma-separated argument restricting the report to feeds with any of the specified tags")
+                 .short("t")
+                 .long("tags")
+                 .value_delimiter(",")))
+        .subcommand(SubCommand::with_name("import")
+            .about("Merge the subscriptions of an OPML file or Newsboat urls file, such as an export from another reader, into the subscription list")
+            .arg(Arg::with_name("file")
+                 .help("The file to import")
+                 .required(true))
+            .arg(arg_subscription_format())
+            .arg(Arg::with_name("tag-from-folder")
+                 .help("Tag imported feeds with the names of the OPML folders containing them")
+                 .long("tag-from-folder"))
+            .arg(arg_allow_local()))
         .subcommand(SubCommand::with_name("link")
             .about("Print the homepage link for a feed provider or the link for a specific entry (works with RSS enclosures)")
             .arg(Arg::with_name("feed")
                  .help("A key by which to search for a feed title")
                  .required(true))
             .arg(Arg::with_name("entry")
-                 .help("An integer indexing the desired entry, starting at zero")))
+                 .help("The reference of the desired entry as printed by list, or an integer indexing it, starting at zero")))
         .subcommand(SubCommand::with_name("list")
             .about("List feeds with their dates of last update, or similarly list entries from specific feeds")
             .arg(Arg::with_name("feed")
@@ -38,7 +145,47 @@ fn main() -> Result<(), Box<dyn Error>> {
                  .help("Print all entries of each matching feed")
                  .short("l")
                  .long("long")
-                 .requires("tags")))
+                 .requires("tags"))
+            .arg(arg_collapse_duplicates()
+                 .requires("long"))
+            .arg(arg_since())
+            .arg(arg_until()))
+        .subcommand(SubCommand::with_name("reindex")
+            .about("Rebuild the search index from the cached feeds"))
+        .subcommand(SubCommand::with_name("restore")
+            .about("Bring back a previous version of a cached feed, such as after a bad update")
+            .arg(Arg::with_name("feed")
+                 .help("A key by which to search for a feed title")
+                 .required(true))
+            .arg(Arg::with_name("backup")
+                 .help("Which backup to restore, counting from 1 for the most recent")
+                 .short("b")
+                 .long("backup")
+                 .takes_value(true)
+                 .default_value("1")))
+        .subcommand(SubCommand::with_name("search")
+            .about("Search the titles and contents of entries in all cached feeds")
+            .arg(Arg::with_name("query")
+                 .help("The text to search for, ignoring case")
+                 .required(true))
+            .arg(Arg::with_name("tags")
+                 .help("A single, comma-separated argument restricting the search to feeds with any of the specified tags")
+                 .short("t")
+                 .long("tags")
+                 .value_delimiter(","))
+            .arg(Arg::with_name("field")
+                 .help("The part of each entry to search")
+                 .short("f")
+                 .long("field")
+                 .possible_values(&["title", "content", "all"])
+                 .default_value("all"))
+            .arg(arg_collapse_duplicates())
+            .arg(Arg::with_name("regex")
+                 .help("Interpret the query as a regular expression")
+                 .short("r")
+                 .long("regex"))
+            .arg(arg_since())
+            .arg(arg_until()))
         .subcommand(SubCommand::with_name("update")
             .about("Update the cached feeds; koifeed never does this automatically")
             .arg(Arg::with_name("feed")
@@ -48,39 +195,175 @@ fn main() -> Result<(), Box<dyn Error>> {
                  .short("t")
                  .long("tags")
                  .conflicts_with("feed")
-                 .value_delimiter(",")))
+                 .value_delimiter(","))
+            .arg(Arg::with_name("force")
+                 .help("Update feeds even if they are not due yet")
+                 .short("f")
+                 .long("force"))
+            .arg(Arg::with_name("fix-redirects")
+                 .help("Replace the URLs of feeds which have moved permanently in the subscription list")
+                 .long("fix-redirects")))
         .get_matches();
 
-    let path_home = env::var("HOME").expect("HOME environment variable inaccessible");
-    // TODO: attempt to create this directory if missing
-    let path_feed_dir = format!("{}/.local/share/koifeed/", path_home);
-    // TODO: warn about missing file gracefully
-    let opml = fs::read_to_string(format!("{}/.config/koifeed/feeds.opml", path_home)).expect("error accessing opml file");
-    let opml = Opml::new(opml)?;
+    let paths = Paths::resolve(arguments.value_of("opml"), arguments.value_of("data-dir"))?;
+    if let ("init", Some(arguments)) = arguments.subcommand() {
+        let import = arguments.value_of("import");
+        return init(
+            &paths,
+            import,
+            arguments.is_present("tag-from-folder"),
+            arguments.is_present("allow-local"),
+            arguments.is_present("force"),
+        );
+    }
+    let path_feed_dir = paths.data_dir.clone();
+    let opml = match fs::read_to_string(&paths.opml) {
+        Ok(opml) => opml,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err(format!("no subscription list at {}; run `koi init` to create one", paths.opml).into());
+        },
+        Err(err) => return Err(format!("{}: {}", paths.opml, err).into()),
+    };
+    let mut opml = Opml::new(opml).map_err(|err| format!("{}: {}", paths.opml, err))?;
+    let path_config = format!("{}/config.toml", paths.config_dir);
+    let config = Config::load(&path_config)?;
+    for key in config.feeds.keys() {
+        if !opml.titles().any(|title| &title == key) && !opml.links_xml().any(|link| &link == key) {
+            eprintln!("warning: {}: feeds.{} matches no feed title or xmlUrl", path_config, key);
+        }
+    }
+    let output = Output {
+        format: match arguments.value_of("output-format") {
+            Some(format) => format.parse()?,
+            None => config.output_format,
+        },
+        date_format: config.date_format.clone(),
+    };
+    let rules = if arguments.is_present("no-filters") {
+        Rules::default()
+    } else {
+        Rules::new(&config.rules, Utc::now()).map_err(|err| format!("{}: {}", path_config, err))?
+    };
+    let find_options = FindOptions {
+        urls: arguments.is_present("match-urls"),
+        fuzzy: arguments.is_present("fuzzy"),
+    };
 
     match arguments.subcommand() {
         ("content", Some(arguments)) => {
             let key = arguments.value_of("feed").unwrap(); // clap-rs guarantees unwrappability
-            let index_string = arguments.value_of("entry").unwrap(); // clap-rs guarantees unwrappability
-            let index = index_string.parse::<usize>().expect("entry must be specified as a nonnegative integer");
-            let title = opml.find(key).expect("invalid feed key");
+            let entry_key = arguments.value_of("entry").unwrap(); // clap-rs guarantees unwrappability
+            let title = opml.find_with(key, find_options)?;
             let feed = init_feed_by_title(&path_feed_dir, &title)?;
-            let contents = feed.contents().nth(index).expect("entry index out of bounds");
-            println!("{}", contents);
+            let (_, entry) = feed
+                .entry(entry_key)
+                .ok_or_else(|| format!("{}: no entry with the reference or index {}", title, entry_key))?;
+            println!("{}", entry.content);
+        },
+        ("diff", Some(arguments)) => {
+            let range = date_range(arguments)?;
+            let titles = match arguments.value_of("feed") {
+                Some(key) => vec![opml.find_with(key, find_options)?],
+                None => {
+                    let given_tags: Option<Vec<_>> = arguments.values_of("tags").map(Iterator::collect);
+                    opml.titles()
+                        .zip(opml.tags())
+                        .filter(|(_, tags)| given_tags.as_ref().is_none_or(|given| tags.iter().any(|tag| given.contains(&&tag[..]))))
+                        .map(|(title, _)| title)
+                        .collect()
+                },
+            };
+            output.header(&["FEED", "CHANGE", "INDEX", "REF    ", "DATE", "TITLE"]);
+            for title in titles {
+                let changes = match FeedChanges::load(&path_feed_dir, &title)? {
+                    Some(changes) => changes,
+                    None => continue,
+                };
+                let feed = cache::read(&path_feed_dir, &title).ok().map(Feed::new);
+                let state = FeedState::load(&path_feed_dir, &title)?;
+                for change in changes.0 {
+                    let entry = Entry { date: change.date.clone(), ..Entry::default() };
+                    if !range.contains(entry.timestamp(), state.updated_previous) {
+                        continue;
+                    }
+                    // Removed entries are no longer cached, so they have no index.
+                    let index = feed
+                        .as_ref()
+                        .filter(|_| change.kind != ChangeKind::Removed)
+                        .and_then(|feed| feed.entries().position(|entry| entry.reference() == change.reference));
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}\t{}",
+                        title,
+                        change.kind.name(),
+                        index.map_or_else(|| String::from("-"), |index| index.to_string()),
+                        change.reference,
+                        output.date(&entry),
+                        change.title,
+                    );
+                }
+            }
+        },
+        ("export", Some(arguments)) => {
+            match arguments.value_of("format") {
+                Some("newsboat") => print!("{}", newsboat::render(&opml.outlines(false))),
+                _ => print!("{}", opml.text()),
+            }
+        },
+        ("health", Some(arguments)) => {
+            let given_tags: Option<Vec<_>> = arguments.values_of("tags").map(Iterator::collect);
+            let now = Utc::now();
+            output.header(&["FEED", "VERDICT", "UPDATED", "FAILURES", "HTTP", "NEWEST", "PER WEEK", "ERROR"]);
+            for (title, tags) in opml.titles().zip(opml.tags()) {
+                if given_tags.as_ref().is_some_and(|given| !tags.iter().any(|tag| given.contains(&&tag[..]))) {
+                    continue;
+                }
+                let state = FeedState::load(&path_feed_dir, &title)?;
+                let feed = cache::read(&path_feed_dir, &title).ok().map(Feed::new);
+                let health = Health::assess(&state, feed.as_ref(), now);
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{:.1}\t{}",
+                    title,
+                    health.verdict.name(),
+                    output.timestamp(state.updated),
+                    state.failures,
+                    state.status.map_or_else(|| String::from("-"), |status| status.to_string()),
+                    health.newest.map_or_else(|| String::from("-"), |newest| format!("{}d", (now - newest).num_days())),
+                    health.per_week,
+                    state.error.as_deref().unwrap_or("-"),
+                );
+            }
+        },
+        ("import", Some(arguments)) => {
+            let path = arguments.value_of("file").unwrap(); // clap-rs guarantees unwrappability
+            let report = match arguments.value_of("format") {
+                Some("newsboat") => {
+                    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+                    let (outlines, warnings) = newsboat::parse(&text);
+                    for warning in warnings {
+                        eprintln!("warning: {}: {}", path, warning);
+                    }
+                    opml.merge_outlines(outlines, arguments.is_present("allow-local"))?
+                },
+                _ => opml.merge(&read_opml(path)?, arguments.is_present("tag-from-folder"), arguments.is_present("allow-local"))?,
+            };
+            if !report.added.is_empty() {
+                fs::write(&paths.opml, opml.text())?;
+            }
+            print_merge_report(&output, &report);
         },
         ("link", Some(arguments)) => {
             let key = arguments.value_of("feed").unwrap(); // clap-rs guarantees unwrappability
-            let title = opml.find(key).expect("invalid feed key");
+            let title = opml.find_with(key, find_options)?;
             match arguments.value_of("entry") {
-                Some(index_string) => {
+                Some(entry_key) => {
                     let feed = init_feed_by_title(&path_feed_dir, &title)?;
-                    let index = index_string.parse::<usize>().expect("entry must be specified as a nonnegative integer");
-                    let mut enclosures = feed.enclosure_links().peekable();
-                    let link = if enclosures.peek().is_none() {
-                        feed.links().nth(index).expect("entry index out of bounds")
-                    } else {
-                        enclosures.nth(index).expect("entry index out of bounds")
-                    };
+                    let (_, entry) = feed
+                        .entry(entry_key)
+                        .ok_or_else(|| format!("{}: no entry with the reference or index {}", title, entry_key))?;
+                    let link = entry
+                        .enclosure
+                        .or(entry.link)
+                        .ok_or_else(|| format!("{}: entry {} has no link", title, entry_key))?;
                     println!("{}", link);
                 },
                 None => {
@@ -88,7 +371,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     let links = opml.links_html();
                     for (feed_title, link) in feed_titles.zip(links) {
                         if title == feed_title {
-                            println!("{}", link.expect("no HTML link found for that feed"));
+                            println!("{}", link.ok_or_else(|| format!("{}: no HTML link found for that feed", title))?);
                             break;
                         }
                     }
@@ -96,18 +379,22 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
         },
         ("list", Some(arguments)) => {
+            let range = date_range(arguments)?;
+            let collapse = arguments.is_present("collapse-duplicates");
             match (arguments.value_of("feed"), arguments.is_present("tags")) {
                 (Some(key), false) => {
-                    let title = opml.find(key).expect("invalid feed key");
-                    println!("feed: {}", title);
+                    let title = opml.find_with(key, find_options)?;
+                    output.heading(&title);
                     let feed = init_feed_by_title(&path_feed_dir, &title)?;
-                    let dates = feed.dates();
-                    let titles = feed.titles();
-                    //               yyyy-mm-dd
-                    println!("INDEX\tDATE      \tTITLE");
-                    for (index, (date, title)) in dates.zip(titles).enumerate() {
-                        println!("{}\t{}\t{}", index, date, title);
-                    }
+                    let state = FeedState::load(&path_feed_dir, &title)?;
+                    let tags = opml
+                        .titles()
+                        .zip(opml.tags())
+                        .find(|(feed_title, _)| *feed_title == title)
+                        .map(|(_, tags)| tags)
+                        .unwrap_or_default();
+                    output.header(&["INDEX", "REF    ", "DATE", "TITLE"]);
+                    print_rows(&output, &entry_rows(&title, &feed, &range, &state, &tags, &rules));
                 },
                 (None, true) => {
                     let search_tags = arguments
@@ -117,47 +404,67 @@ fn main() -> Result<(), Box<dyn Error>> {
                     let titles = opml.titles();
                     let long_flag_set = arguments.is_present("long");
                     if !long_flag_set {
-                        //        yyyy-mm-dd
-                        println!("DATE      \tFEED");
+                        output.header(&["DATE", "FEED"]);
                     }
+                    let mut listed = Vec::new();
+                    let mut rows = Vec::new();
                     for (title, tags) in titles.zip(tags) {
                         //println!("DEBUG: title: {}; tags: {:?}", title, tags);
                         for search_tag in search_tags.clone() {
                             let search_string = String::from(search_tag);
                             if tags.contains(&search_string) {
-                                let feed = init_feed_by_title(&path_feed_dir, &title)?;
-                                let dates = feed.dates();
+                                let feed = match init_feed_by_title(&path_feed_dir, &title) {
+                                    Ok(feed) => feed,
+                                    Err(err) => {
+                                        eprintln!("warning: {}", err);
+                                        break;
+                                    },
+                                };
+                                let state = FeedState::load(&path_feed_dir, &title)?;
                                 if arguments.is_present("long") {
-                                    println!("feed: {}", title);
-                                    println!("-----");
-                                    //               yyyy-mm-dd
-                                    println!("INDEX\tDATE      \tTITLE");
-                                    let entry_titles = feed.titles();
-                                    for (index, (date, entry_title)) in dates.zip(entry_titles).enumerate() {
-                                        println!("{}\t{}\t{}", index, date, entry_title);
-                                    }
-                                    println!();
+                                    rows.extend(entry_rows(&title, &feed, &range, &state, &tags, &rules));
+                                    listed.push(title.clone());
                                 } else {
-                                    let feed = init_feed_by_title(&path_feed_dir, &title)?;
-                                    let date = feed.dates().next().expect("no entries found");
-                                    println!("{}\t{}", date, title);
-                                    break;
+                                    let entries = feed.entries().filter(|entry| !rules.hides(&title, &tags, entry));
+                                    if let Some(entry) = range.first(entries, state.updated_previous) {
+                                        println!("{}\t{}", output.date(&entry), title);
+                                    }
                                 }
+                                break;
                             }
                         }
                     }
+                    // Printed only once all feeds are read, so that duplicates can be collapsed
+                    // into the first feed carrying them.
+                    let rows = if collapse { collapse_duplicates(rows) } else { rows };
+                    for title in listed {
+                        output.heading(&title);
+                        output.rule();
+                        output.header(if collapse {
+                            &["INDEX", "REF    ", "DATE", "TITLE", "ALSO IN"]
+                        } else {
+                            &["INDEX", "REF    ", "DATE", "TITLE"]
+                        });
+                        let rows: Vec<Row> = rows.iter().filter(|row| row.feed == title).cloned().collect();
+                        print_rows(&output, &rows);
+                        output.separator();
+                    }
                 },
                 (None, false) => { // list all feeds
-                    let titles = opml.titles();
-                    //        yyyy-mm-dd
-                    println!("DATE      \tTITLE");
-                    for title in titles {
-                        let feed = init_feed_by_title(&path_feed_dir, &title)?;
-                        let date = feed
-                            .dates()
-                            .next()
-                            .expect("no entries found");
-                        println!("{}\t{}", date, title);
+                    output.header(&["DATE", "TITLE"]);
+                    for (title, tags) in opml.titles().zip(opml.tags()) {
+                        let feed = match init_feed_by_title(&path_feed_dir, &title) {
+                            Ok(feed) => feed,
+                            Err(err) => {
+                                eprintln!("warning: {}", err);
+                                continue;
+                            },
+                        };
+                        let state = FeedState::load(&path_feed_dir, &title)?;
+                        let entries = feed.entries().filter(|entry| !rules.hides(&title, &tags, entry));
+                        if let Some(entry) = range.first(entries, state.updated_previous) {
+                            println!("{}\t{}", output.date(&entry), title);
+                        }
                     }
                 },
                 _ => {
@@ -167,34 +474,108 @@ fn main() -> Result<(), Box<dyn Error>> {
                 }
             }
         },
+        ("reindex", Some(_)) => {
+            FeedIndex::clear(&path_feed_dir)?;
+            for title in opml.titles() {
+                let feed = match init_feed_by_title(&path_feed_dir, &title) {
+                    Ok(feed) => feed,
+                    Err(err) => {
+                        eprintln!("warning: {}", err);
+                        continue;
+                    },
+                };
+                eprintln!("indexing {}", title);
+                FeedIndex::new(&feed).save(&path_feed_dir, &title)?;
+            }
+        },
+        ("restore", Some(arguments)) => {
+            let key = arguments.value_of("feed").unwrap(); // clap-rs guarantees unwrappability
+            let generation = match arguments.value_of("backup").unwrap().parse::<usize>() { // clap-rs guarantees unwrappability
+                Ok(generation) if generation > 0 => generation,
+                _ => return Err("the backup to restore must be a positive number".into()),
+            };
+            let title = opml.find_with(key, find_options)?;
+            let text = match cache::restore(&path_feed_dir, &title, generation, config.retention, config.compress) {
+                Ok(text) => text,
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    return Err(format!("no backup {} of {}; see `retention` in {}", generation, title, path_config).into());
+                },
+                Err(err) => return Err(format!("{}: {}", cache::backup_path(&path_feed_dir, &title, generation), err).into()),
+            };
+            FeedIndex::new(&Feed::new(text)).save(&path_feed_dir, &title)?;
+            eprintln!("restored {} from backup {}", title, generation);
+        },
+        ("search", Some(arguments)) => {
+            let range = date_range(arguments)?;
+            let text = arguments.value_of("query").unwrap(); // clap-rs guarantees unwrappability
+            let field = arguments.value_of("field").unwrap().parse::<Field>()?; // clap-rs provides a default
+            let query = if arguments.is_present("regex") {
+                Query::regex(text, field)?
+            } else {
+                Query::substring(text, field)
+            };
+            let search_tags: Vec<_> = arguments
+                .values_of("tags")
+                .map(|values| values.collect())
+                .unwrap_or_default();
+            let collapse = arguments.is_present("collapse-duplicates");
+            output.header(if collapse {
+                &["FEED", "INDEX", "REF    ", "DATE", "TITLE", "ALSO IN"]
+            } else {
+                &["FEED", "INDEX", "REF    ", "DATE", "TITLE"]
+            });
+            let mut rows = Vec::new();
+            for (title, tags) in opml.titles().zip(opml.tags()) {
+                if !search_tags.is_empty() && !tags.iter().any(|tag| search_tags.contains(&&tag[..])) {
+                    continue;
+                }
+                // Feeds missing from the index are searched in full.
+                let candidates = FeedIndex::load(&path_feed_dir, &title)?
+                    .and_then(|index| index.candidates(&query));
+                if matches!(&candidates, Some(candidates) if candidates.is_empty()) {
+                    continue;
+                }
+                let feed = match init_feed_by_title(&path_feed_dir, &title) {
+                    Ok(feed) => feed,
+                    Err(err) => {
+                        eprintln!("warning: {}", err);
+                        continue;
+                    },
+                };
+                let state = FeedState::load(&path_feed_dir, &title)?;
+                for (index, entry) in feed.entries().enumerate() {
+                    let candidate = candidates
+                        .as_ref()
+                        .is_none_or(|candidates| candidates.contains(&index));
+                    if candidate
+                        && range.contains(entry.timestamp(), state.updated_previous)
+                        && query.matches(&entry)
+                        && !rules.hides(&title, &tags, &entry)
+                    {
+                        let row = Row { feed: title.clone(), index, entry, also: None };
+                        // Duplicates can only be collapsed once every feed has been searched.
+                        if collapse {
+                            rows.push(row);
+                        } else {
+                            println!("{}\t{}", row.feed, row.columns(&output));
+                        }
+                    }
+                }
+            }
+            for row in collapse_duplicates(rows) {
+                println!("{}\t{}", row.feed, row.columns(&output));
+            }
+        },
         ("update", Some(arguments)) => {
-            /*
-            .arg(Arg::with_name("feed")
-            .arg(Arg::with_name("tags")
-                 .conflicts_with("feed")
-                 .value_delimiter(",")))
-             */
-            let client = Client::new();
+            let mut selected = Vec::new();
             match (arguments.value_of("feed"), arguments.is_present("tags")) {
                 (Some(key), false) => {
-                    let search_title = opml.find(key).expect("invalid feed key");
+                    let search_title = opml.find_with(key, find_options)?;
                     let titles = opml.titles();
                     let links_xml = opml.links_xml();
                     for (title, link_xml) in titles.zip(links_xml) {
                         if title == search_title {
-                            match client.get(&link_xml).send() {
-                                Ok(response) => match response.text() {
-                                    Ok(string) => {
-                                        fs::write(format!("{}/{}", path_feed_dir, title), string)?;
-                                    },
-                                    Err(_) => {
-                                        eprintln!("error: empty response from {}", link_xml);
-                                    },
-                                },
-                                Err(_) => {
-                                    eprintln!("error: request failure for {}", link_xml);
-                                },
-                            }
+                            selected.push((title, link_xml));
                             break;
                         }
                     }
@@ -205,50 +586,38 @@ fn main() -> Result<(), Box<dyn Error>> {
                     let links_xml = opml.links_xml();
                     let tag_lists = opml.tags();
                     for ((title, link_xml), tags) in titles.zip(links_xml).zip(tag_lists) {
-                        for tag in tags {
-                            if given_tags.contains(&&tag[..]) {
-                                eprintln!("updating {}", title);
-                                match client.get(&link_xml).send() {
-                                    Ok(response) => match response.text() {
-                                        Ok(string) => {
-                                            fs::write(format!("{}/{}", path_feed_dir, title), string)?;
-                                        },
-                                        Err(_) => {
-                                            eprintln!("error: empty response from {}", link_xml);
-                                        },
-                                    },
-                                    Err(_) => {
-                                        eprintln!("error: request failure for {}", link_xml);
-                                    },
-                                }
-                                break;
-                            }
+                        if tags.iter().any(|tag| given_tags.contains(&&tag[..])) {
+                            selected.push((title, link_xml));
                         }
                     }
                 },
                 (None, false) => {
-                    let titles = opml.titles();
-                    let links_xml = opml.links_xml();
-                    for (title, link_xml) in titles.zip(links_xml) {
-                        eprintln!("updating {}", title);
-                        let body = match client.get(&link_xml).send() {
-                            Ok(response) => match response.text() {
-                                Ok(string) => string,
-                                Err(_) => {
-                                    eprintln!("\terror: empty response from {}", link_xml);
-                                    continue;
-                                },
-                            },
-                            Err(_) => {
-                                eprintln!("\terror: request failure for {}", link_xml);
-                                continue;
-                            },
-                        };
-                        fs::write(&format!("{}/{}", path_feed_dir, title), body)?;
-                    }
+                    selected.extend(opml.titles().zip(opml.links_xml()));
                 },
                 _ => {}, // all other cases have already been handled by clap-rs.
             }
+            fs::create_dir_all(&path_feed_dir)?;
+            let client = build_client(&config, None)?;
+            update_feeds(&client, &config, &path_feed_dir, selected.clone(), arguments.is_present("force"))?;
+            let mut moved_any = false;
+            for (title, link_xml) in selected {
+                let moved = match FeedState::load(&path_feed_dir, &title)?.moved {
+                    Some(moved) if moved != link_xml => moved,
+                    _ => continue,
+                };
+                if arguments.is_present("fix-redirects") {
+                    opml.set_link_xml(&link_xml, &moved)?;
+                    eprintln!("{}: xmlUrl changed to {}", title, moved);
+                }
+                moved_any = true;
+            }
+            if moved_any {
+                if arguments.is_present("fix-redirects") {
+                    fs::write(&paths.opml, opml.text())?;
+                } else {
+                    eprintln!("note: some feeds have moved; run `koi update --fix-redirects` to update {}", paths.opml);
+                }
+            }
         },
         _ => {}, // should never be hit
     }
@@ -257,7 +626,531 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 fn init_feed_by_title(dir_path: &str, title: &str) -> Result<Feed, Box<dyn Error>> {
-    let feed_path = format!("{}/{}", dir_path, title);
-    let text = fs::read_to_string(feed_path)?;
-    Ok(Feed::new(text))
+    match cache::read(dir_path, title) {
+        Ok(text) => Ok(Feed::new(text)),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if fs::metadata(dir_path).is_err() {
+                Err(format!("no feed directory at {}; run `koi init` to create one", dir_path).into())
+            } else {
+                Err(format!("no cached copy of {}; run `koi update` to fetch it", title).into())
+            }
+        },
+        Err(err) => Err(format!("{}: {}", cache::path(dir_path, title), err).into()),
+    }
+}
+
+/// Reads and checks an OPML file given on the command line.
+fn read_opml(path: &str) -> Result<Opml, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    Ok(Opml::new(text).map_err(|err| format!("{}: {}", path, err))?)
+}
+
+/// Prints what became of each subscription considered by an import.
+fn print_merge_report(output: &Output, report: &MergeReport) {
+    output.header(&["STATUS  ", "TITLE", "URL", "NOTE"]);
+    for outline in &report.added {
+        println!("added   \t{}\t{}\t", outline.title, outline.xml_url);
+    }
+    for (outline, reason) in &report.skipped {
+        println!("skipped \t{}\t{}\t{}", outline.title, outline.xml_url, reason);
+    }
+    for (outline, reason) in &report.conflicting {
+        println!("conflict\t{}\t{}\t{}", outline.title, outline.xml_url, reason);
+    }
+}
+
+/// Sets up koifeed's directories and subscription list, optionally starting from an OPML file
+/// exported by another reader. An existing subscription list is only replaced if forced.
+fn init(paths: &Paths, import: Option<&str>, folder_tags: bool, local: bool, force: bool) -> Result<(), Box<dyn Error>> {
+    let mut opml = Opml::empty();
+    if let Some(path) = import {
+        // Merging flattens folders, which the rest of koifeed does not understand.
+        let report = opml.merge(&read_opml(path)?, folder_tags, local)?;
+        for (outline, reason) in report.skipped.iter().chain(&report.conflicting) {
+            eprintln!("skipping {} ({}): {}", outline.title, outline.xml_url, reason);
+        }
+    }
+    let opml_exists = fs::metadata(&paths.opml).is_ok();
+    if opml_exists && !force && import.is_some() {
+        return Err(format!("{} already exists; pass --force to replace it", paths.opml).into());
+    }
+    fs::create_dir_all(&paths.config_dir)?;
+    fs::create_dir_all(&paths.data_dir)?;
+    eprintln!("feeds are cached in {}", paths.data_dir);
+    if opml_exists && !force {
+        eprintln!("keeping the existing subscription list at {}", paths.opml);
+    } else {
+        if let Some(parent) = Path::new(&paths.opml).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&paths.opml, opml.text())?;
+        eprintln!("wrote {} subscriptions to {}", opml.titles().count(), paths.opml);
+    }
+    Ok(())
+}
+
+/// The "format" argument shared by the commands reading and writing subscription lists.
+fn arg_subscription_format<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("format")
+        .help("The format of the subscription list")
+        .long("format")
+        .possible_values(&["opml", "newsboat"])
+        .default_value("opml")
+}
+
+/// The "allow-local" argument shared by the commands importing subscription lists.
+fn arg_allow_local<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("allow-local")
+        .help("Also import subscriptions to local files (file:) and commands (exec:), which run on every update; only use with trusted files")
+        .long("allow-local")
+}
+
+/// The "collapse-duplicates" argument shared by the commands listing entries of several feeds.
+fn arg_collapse_duplicates<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("collapse-duplicates")
+        .help("Show entries carried by several feeds once, under the first of them, along with the others")
+        .short("d")
+        .long("collapse-duplicates")
+}
+
+/// The "since" argument shared by all commands listing entries.
+fn arg_since<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("since")
+        .help("Only list entries dated on or after this: yyyy-mm-dd, an RFC 3339 timestamp, a duration back from now (12h, 3d, 2w), or last-update")
+        .long("since")
+        .takes_value(true)
+}
+
+/// The "until" argument shared by all commands listing entries.
+fn arg_until<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("until")
+        .help("Only list entries dated on or before this; takes the same forms as --since")
+        .long("until")
+        .takes_value(true)
+}
+
+/// Builds the date range given by the "since" and "until" arguments of a listing command.
+fn date_range(arguments: &ArgMatches) -> Result<DateRange, Box<dyn Error>> {
+    let now = Utc::now();
+    let since = arguments
+        .value_of("since")
+        .map(|text| DateBound::parse(text, now, false))
+        .transpose()?;
+    let until = arguments
+        .value_of("until")
+        .map(|text| DateBound::parse(text, now, true))
+        .transpose()?;
+    Ok(DateRange { since, until })
+}
+
+/// How listings are printed.
+struct Output {
+    format: OutputFormat,
+    date_format: String,
+}
+
+impl Output {
+    /// Prints the header line of a table. The date column is padded to the width of the dates
+    /// below it.
+    fn header(&self, columns: &[&str]) {
+        if self.format != OutputFormat::Table {
+            return;
+        }
+        let date_width = Entry {
+            date: Some(String::from("2000-12-31T23:59:59Z")),
+            ..Entry::default()
+        }.date_formatted(&self.date_format).chars().count();
+        let columns: Vec<String> = columns
+            .iter()
+            .map(|column| match *column {
+                "DATE" | "UPDATED" => format!("{:width$}", column, width = date_width),
+                _ => String::from(*column),
+            })
+            .collect();
+        println!("{}", columns.join("\t"));
+    }
+
+    /// Prints the heading above the entries of a single feed.
+    fn heading(&self, title: &str) {
+        if self.format == OutputFormat::Table {
+            println!("feed: {}", title);
+        }
+    }
+
+    /// Prints the rule separating a heading from what follows when several feeds are listed.
+    fn rule(&self) {
+        if self.format == OutputFormat::Table {
+            println!("-----");
+        }
+    }
+
+    /// Prints the blank line following the entries of a single feed.
+    fn separator(&self) {
+        if self.format == OutputFormat::Table {
+            println!();
+        }
+    }
+
+    /// Formats the date of an entry.
+    fn date(&self, entry: &Entry) -> String {
+        entry.date_formatted(&self.date_format)
+    }
+
+    /// Formats a point in time in local time, or a dash if there is none.
+    fn timestamp(&self, timestamp: Option<DateTime<Utc>>) -> String {
+        match timestamp {
+            Some(timestamp) => timestamp.with_timezone(&Local).format(&self.date_format).to_string(),
+            None => String::from("-"),
+        }
+    }
+}
+
+/// An entry to be listed, along with the titles of the other feeds which carried it if duplicates
+/// are collapsed.
+#[derive(Clone)]
+struct Row {
+    feed: String,
+    index: usize,
+    entry: Entry,
+    also: Option<Vec<String>>,
+}
+
+impl Row {
+    /// Formats the columns of the row other than the feed title.
+    fn columns(&self, output: &Output) -> String {
+        let entry = &self.entry;
+        let row = format!("{}\t{}\t{}\t{}", self.index, entry.reference(), output.date(entry), entry.title);
+        match &self.also {
+            Some(also) if also.is_empty() => format!("{}\t-", row),
+            Some(also) => format!("{}\t{}", row, also.join(",")),
+            None => row,
+        }
+    }
+}
+
+/// Collects those entries of a feed which fall inside the given range and are not hidden by the
+/// rules. Indexes are those of the unfiltered feed, so they remain valid arguments to the other
+/// subcommands until the next update; references remain valid for good.
+fn entry_rows(title: &str, feed: &Feed, range: &DateRange, state: &FeedState, tags: &[String], rules: &Rules) -> Vec<Row> {
+    feed.entries()
+        .enumerate()
+        .filter(|(_, entry)| range.contains(entry.timestamp(), state.updated_previous) && !rules.hides(title, tags, entry))
+        .map(|(index, entry)| Row { feed: String::from(title), index, entry, also: None })
+        .collect()
+}
+
+/// Prints the entries of a single feed. Bare values are prefixed with the feed title, since they
+/// lack the heading a table has.
+fn print_rows(output: &Output, rows: &[Row]) {
+    for row in rows {
+        match output.format {
+            OutputFormat::Table => println!("{}", row.columns(output)),
+            OutputFormat::Tsv => println!("{}\t{}", row.feed, row.columns(output)),
+        }
+    }
+}
+
+/// Keeps only the first of each group of duplicate entries (see the `duplicates` module), noting
+/// the other feeds which carried it.
+fn collapse_duplicates(rows: Vec<Row>) -> Vec<Row> {
+    let groups = duplicates::groups(rows.iter().map(|row| (&row.feed[..], &row.entry)));
+    let mut also = vec![Vec::new(); rows.len()];
+    for (position, group) in groups.iter().enumerate() {
+        let feed = &rows[position].feed;
+        if *feed != rows[*group].feed && !also[*group].contains(feed) {
+            also[*group].push(feed.clone());
+        }
+    }
+    rows.into_iter()
+        .zip(also)
+        .enumerate()
+        .filter(|(position, _)| groups[*position] == *position)
+        .map(|(_, (row, also))| Row { also: Some(also), ..row })
+        .collect()
+}
+
+/// Builds the client used for requests from the global settings, overridden by those of a feed that
+/// needs its own connection (see `FeedConfig::has_own_connection()`).
+fn build_client(config: &Config, feed_config: Option<&FeedConfig>) -> Result<Client, Box<dyn Error>> {
+    let setting = |global: &Option<String>, feed: fn(&FeedConfig) -> &Option<String>| {
+        feed_config.and_then(|feed_config| feed(feed_config).clone()).or_else(|| global.clone())
+    };
+    // Redirects are followed by hand, so that permanent ones can be told apart.
+    let mut builder = Client::builder().redirect(Policy::none());
+    if let Some(user_agent) = &config.user_agent {
+        builder = builder.user_agent(user_agent.clone());
+    }
+    if let Some(timeout) = config.timeout {
+        builder = builder.timeout(Duration::from_secs(timeout));
+    }
+    match setting(&config.proxy, |feed| &feed.proxy).as_deref() {
+        Some("") => builder = builder.no_proxy(),
+        Some(proxy) => builder = builder.proxy(Proxy::all(proxy)?),
+        None => {},
+    }
+    if let Some(path) = setting(&config.ca_bundle, |feed| &feed.ca_bundle) {
+        let pem = fs::read(&path).map_err(|err| format!("{}: {}", path, err))?;
+        for certificate in Certificate::from_pem_bundle(&pem).map_err(|err| format!("{}: {}", path, err))? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    // A certificate and its key always come from the same place.
+    let (client_cert, client_key) = match feed_config.filter(|feed_config| feed_config.client_cert.is_some()) {
+        Some(feed_config) => (&feed_config.client_cert, &feed_config.client_key),
+        None => (&config.client_cert, &config.client_key),
+    };
+    if let (Some(cert_path), Some(key_path)) = (client_cert, client_key) {
+        let cert = fs::read(cert_path).map_err(|err| format!("{}: {}", cert_path, err))?;
+        let key = fs::read(key_path).map_err(|err| format!("{}: {}", key_path, err))?;
+        let identity = Identity::from_pkcs8_pem(&cert, &key).map_err(|err| format!("{}: {}", cert_path, err))?;
+        builder = builder.identity(identity);
+    }
+    Ok(builder.build()?)
+}
+
+/// Updates the given feeds, fetching as many at once as the configuration allows. Disabled feeds
+/// are skipped, as are feeds which are not due yet unless `force` is set. The recorded changes of
+/// every given feed are cleared first, so that only those which update have any afterwards.
+fn update_feeds(client: &Client, config: &Config, dir_path: &str, feeds: Vec<(String, String)>, force: bool) -> Result<(), Box<dyn Error>> {
+    let workers = config.concurrency.min(feeds.len()).max(1);
+    let queue = Mutex::new(feeds.into_iter());
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| scope.spawn(|| -> Result<(), String> {
+                loop {
+                    let next = queue.lock().expect("update queue poisoned").next();
+                    let (title, link_xml) = match next {
+                        Some(feed) => feed,
+                        None => return Ok(()),
+                    };
+                    FeedChanges::clear(dir_path, &title).map_err(|err| err.to_string())?;
+                    let feed_config = config.feed(&title, &link_xml);
+                    if feed_config.disabled {
+                        eprintln!("skipping {} (disabled)", title);
+                        continue;
+                    }
+                    if !force {
+                        let state = FeedState::load(dir_path, &title).map_err(|err| err.to_string())?;
+                        match state.next_due(feed_config.interval()) {
+                            Some(due) if due > Utc::now() => {
+                                eprintln!("skipping {} (not due until {})", title, due.with_timezone(&Local).format("%Y-%m-%d %H:%M"));
+                                continue;
+                            },
+                            _ => {},
+                        }
+                    }
+                    let own_client;
+                    let client = if feed_config.has_own_connection() {
+                        own_client = match build_client(config, Some(&feed_config)) {
+                            Ok(client) => client,
+                            Err(err) => {
+                                eprintln!("skipping {} (cannot set up its connection: {})", title, err);
+                                continue;
+                            },
+                        };
+                        &own_client
+                    } else {
+                        client
+                    };
+                    update_feed(client, config, &feed_config, dir_path, &title, &link_xml)
+                        .map_err(|err| err.to_string())?;
+                }
+            }))
+            .collect();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("update thread panicked"))
+    })?;
+    Ok(())
+}
+
+/// Fetches a single feed and overwrites its cache and search index. Failures, including error
+/// responses and anything other than a feed in the response, are reported, recorded in the state of
+/// the feed and skipped, leaving the cache untouched, so that one broken feed does not stop the
+/// others from updating.
+fn update_feed(client: &Client, config: &Config, feed_config: &FeedConfig, dir_path: &str, title: &str, link_xml: &str) -> Result<(), Box<dyn Error>> {
+    eprintln!("updating {}", title);
+    let now = Utc::now();
+    let mut state = FeedState::load(dir_path, title)?;
+    let fetched = match read_local(link_xml) {
+        Some(body) => {
+            state.status = None;
+            body.map(|body| (body, None, None, None))
+        },
+        None => feed_config.auth.as_ref().map(Auth::credentials).transpose().and_then(|credentials| {
+            fetch(client, config, feed_config, credentials.as_ref(), link_xml, &mut state.status)
+        }).and_then(|(response, moved)| {
+            let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from);
+            let lifetime = schedule::cache_lifetime(header(CACHE_CONTROL).as_deref(), header(EXPIRES).as_deref(), now);
+            let content_type = header(CONTENT_TYPE);
+            let body = response.bytes().map_err(|err| describe_error(&err))?;
+            Ok((body.to_vec(), content_type, lifetime, moved))
+        }),
+    };
+    // Filters may change the encoding of the body, after which the Content-Type header no longer
+    // describes it.
+    let fetched = fetched.and_then(|(body, content_type, lifetime, moved)| {
+        let content_type = content_type.filter(|_| feed_config.filters.is_empty());
+        let body = feed_config
+            .filters
+            .iter()
+            .try_fold(body, |body, filter| command::run(filter, Some(&body)))?;
+        let feed = Feed::new_check_format(charset::decode(&body, content_type.as_deref()))?;
+        Ok((feed, lifetime, moved))
+    });
+    let (feed, lifetime, moved) = match fetched {
+        Ok(fetched) => fetched,
+        Err(err) => {
+            eprintln!("\terror: {}: {}", link_xml, err);
+            state.record_failure(now, &err);
+            if state.failures >= health::FAILURES_DEAD {
+                eprintln!("\twarning: {} has failed {} times in a row; see `koi health`", title, state.failures);
+            }
+            state.save(dir_path, title)?;
+            return Ok(());
+        },
+    };
+    let changes = FeedChanges::compare(cache::read(dir_path, title).ok().map(Feed::new).as_ref(), &feed);
+    cache::write(dir_path, title, feed.text(), config.retention, config.compress)?;
+    FeedIndex::new(&feed).save(dir_path, title)?;
+    changes.save(dir_path, title)?;
+    state.record_update(now);
+    state.due = schedule::next_due(now, &[lifetime, schedule::feed_interval(&feed)]);
+    if let Some(moved) = &moved {
+        eprintln!("\tmoved permanently to {}", moved);
+    }
+    state.moved = moved;
+    state.save(dir_path, title)?;
+    Ok(())
+}
+
+/// Reads a feed from a local source: a `file://` URL, or an `exec:` command whose standard output
+/// is the feed. Returns `None` for any other URL.
+fn read_local(link_xml: &str) -> Option<Result<Vec<u8>, String>> {
+    if let Some(command) = link_xml.strip_prefix("exec:") {
+        return Some(command::run(command, None));
+    }
+    if !link_xml.starts_with("file:") {
+        return None;
+    }
+    Some(match Url::parse(link_xml).ok().and_then(|url| url.to_file_path().ok()) {
+        Some(path) => fs::read(&path).map_err(|err| format!("{}: {}", path.display(), err)),
+        None => Err(format!("invalid file URL: {}", link_xml)),
+    })
+}
+
+/// Requests a feed, retrying transient failures (connection errors, timeouts, and `429` or `5xx`
+/// responses) up to `config.retries` times with exponentially growing pauses in between. Responses
+/// other than `2xx` ones are errors. The status code of the last response received is stored in
+/// `status`, or `None` if there was none. Also returns the URL the feed has permanently moved to,
+/// if any.
+fn fetch(client: &Client, config: &Config, feed_config: &FeedConfig, credentials: Option<&Credentials>, link_xml: &str, status: &mut Option<u16>) -> Result<(Response, Option<String>), String> {
+    let mut attempt = 0;
+    loop {
+        *status = None;
+        let (error, transient) = match follow(client, config, feed_config, credentials, link_xml) {
+            Ok((response, moved)) => {
+                let code = response.status();
+                *status = Some(code.as_u16());
+                if code.is_success() {
+                    return Ok((response, moved));
+                }
+                (format!("HTTP {}", code), code == StatusCode::TOO_MANY_REQUESTS || code.is_server_error())
+            },
+            Err(err) => err,
+        };
+        if !transient || attempt >= config.retries {
+            return Err(error);
+        }
+        let pause = Duration::from_secs(1 << attempt.min(6));
+        eprintln!("\tretrying {} in {}s: {}", link_xml, pause.as_secs(), error);
+        thread::sleep(pause);
+        attempt += 1;
+    }
+}
+
+/// Sends a request for a feed, following at most `MAX_REDIRECTS` redirects. Also returns where the
+/// feed has moved to if every redirect leading there was permanent (`301` or `308`); temporary
+/// redirects leave the feed where it is. Credentials are only sent to the origin of the feed
+/// itself, never to where it redirects elsewhere. Errors come with whether they are worth retrying.
+fn follow(client: &Client, config: &Config, feed_config: &FeedConfig, credentials: Option<&Credentials>, link_xml: &str) -> Result<(Response, Option<String>), (String, bool)> {
+    let mut visited = vec![String::from(link_xml)];
+    let mut moved = None;
+    let mut permanent = true;
+    loop {
+        let url = visited.last().unwrap(); // never empty
+        let mut request = client.get(url);
+        if let Some(user_agent) = &feed_config.user_agent {
+            request = request.header(USER_AGENT, user_agent.as_str());
+        }
+        if let Some(timeout) = feed_config.timeout {
+            request = request.timeout(Duration::from_secs(timeout));
+        }
+        let global_headers = config
+            .headers
+            .iter()
+            .filter(|(name, _)| !feed_config.headers.keys().any(|feed_name| feed_name.eq_ignore_ascii_case(name)));
+        for (name, value) in global_headers.chain(&feed_config.headers) {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        if let Some(credentials) = credentials.filter(|_| same_origin(link_xml, url)) {
+            request = match credentials {
+                Credentials::Basic { username, password } => request.basic_auth(username, Some(password)),
+                Credentials::Bearer(token) => request.bearer_auth(token),
+            };
+        }
+        let response = request
+            .send()
+            .map_err(|err| (describe_error(&err), err.is_timeout() || err.is_connect()))?;
+        let code = response.status();
+        if !code.is_redirection() || code == StatusCode::NOT_MODIFIED {
+            return Ok((response, moved));
+        }
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| (format!("HTTP {} without a Location header", code), false))?;
+        let next = response
+            .url()
+            .join(location)
+            .map_err(|err| (format!("invalid redirect to {}: {}", location, err), false))?
+            .to_string();
+        permanent &= code == StatusCode::MOVED_PERMANENTLY || code == StatusCode::PERMANENT_REDIRECT;
+        if permanent {
+            moved = Some(next.clone());
+        }
+        let looped = visited.contains(&next);
+        visited.push(next);
+        if looped {
+            return Err((format!("redirect loop: {}", visited.join(" -> ")), false));
+        }
+        if visited.len() > MAX_REDIRECTS {
+            return Err((format!("more than {} redirects", MAX_REDIRECTS), false));
+        }
+    }
+}
+
+/// Checks whether two URLs share their scheme, host and port.
+fn same_origin(a: &str, b: &str) -> bool {
+    match (Url::parse(a), Url::parse(b)) {
+        (Ok(a), Ok(b)) => a.origin() == b.origin(),
+        _ => false,
+    }
+}
+
+/// Describes an error along with the chain of errors that caused it, which is where reqwest keeps
+/// details such as DNS and TLS failures.
+fn describe_error(err: &dyn Error) -> String {
+    let mut description = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        let cause = err.to_string();
+        if !description.contains(&cause) {
+            description.push_str(": ");
+            description.push_str(&cause);
+        }
+        source = err.source();
+    }
+    description
 }
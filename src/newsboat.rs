@@ -0,0 +1,140 @@
+//! Conversion between subscriptions and Newsboat's `urls` file, in which each line holds a feed
+//! URL followed by its tags, any of which may be quoted. A tag of the form `~Title` overrides the
+//! title of the feed and a tag starting with `!` hides it; koifeed keeps the former and ignores
+//! the latter.
+
+use crate::{title_from_url, Outline};
+
+/// Reads the subscriptions from the contents of a `urls` file, including `exec:` feeds. Lines
+/// which cannot be turned into subscriptions, such as `query:` feeds (which select articles from
+/// other feeds rather than naming one) and `filter:` feeds (whose filter belongs in the
+/// configuration instead), are skipped; a warning describing each such line is returned
+/// alongside.
+pub fn parse(text: &str) -> (Vec<Outline>, Vec<String>) {
+    let mut outlines = Vec::new();
+    let mut warnings = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = split_words(line).into_iter();
+        let xml_url = match words.next() {
+            Some(xml_url) => xml_url,
+            None => continue,
+        };
+        if xml_url.starts_with("filter:") {
+            warnings.push(format!(
+                "line {}: skipping filter feed: {}; subscribe to its URL and set `filters` for it in config.toml instead",
+                number + 1,
+                xml_url,
+            ));
+            continue;
+        }
+        if !["http://", "https://", "file://", "exec:"].iter().any(|scheme| xml_url.starts_with(scheme)) {
+            let kind = xml_url.split(':').next().unwrap_or(&xml_url);
+            warnings.push(format!("line {}: skipping unsupported {} feed: {}", number + 1, kind, xml_url));
+            continue;
+        }
+        let mut title = None;
+        let mut tags = Vec::new();
+        for word in words {
+            if let Some(custom_title) = word.strip_prefix('~') {
+                title = Some(String::from(custom_title));
+            } else if !word.starts_with('!') && !word.is_empty() {
+                tags.push(word);
+            }
+        }
+        let title = title
+            .unwrap_or_else(|| title_from_url(&xml_url))
+            .replace('/', "-");
+        outlines.push(Outline { title, xml_url, html_url: None, tags });
+    }
+    (outlines, warnings)
+}
+
+/// Renders subscriptions as the contents of a `urls` file, keeping each title as a `~` tag.
+pub fn render(outlines: &[Outline]) -> String {
+    let mut text = String::new();
+    for outline in outlines {
+        text.push_str(&outline.xml_url);
+        for tag in &outline.tags {
+            text.push(' ');
+            text.push_str(&quote(tag));
+        }
+        text.push(' ');
+        text.push_str(&quote(&format!("~{}", outline.title)));
+        text.push('\n');
+    }
+    text
+}
+
+/// Splits a line into whitespace-separated words, where double quotes group words containing
+/// whitespace and a backslash escapes the following character.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    let mut characters = line.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            },
+            '\\' => {
+                if let Some(escaped) = characters.next() {
+                    word.get_or_insert_with(String::new).push(escaped);
+                }
+            },
+            _ if character.is_whitespace() && !quoted => {
+                words.extend(word.take());
+            },
+            _ => word.get_or_insert_with(String::new).push(character),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// Quotes a word if it would otherwise be split or misread.
+fn quote(word: &str) -> String {
+    if word.chars().any(|c| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        String::from(word)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static URLS: &str = r#"
+# comment
+https://neovim.io/news.xml software "~Neovim News"
+http://rss.slashdot.org/Slashdot/slashdotMain news "open source" !hidden
+"query:Unread Articles:unread = \"yes\""
+exec:~/bin/scrape.sh
+filter:~/bin/fix.sh:https://example.com/feed.xml news
+"#;
+
+    #[test]
+    fn urls_parse() {
+        let (outlines, warnings) = parse(URLS);
+        assert_eq!(outlines.len(), 3);
+        assert_eq!(outlines[0].title, "Neovim News");
+        assert_eq!(outlines[0].tags, vec![String::from("software")]);
+        assert_eq!(outlines[1].title, "rss.slashdot.org-Slashdot-slashdotMain");
+        assert_eq!(outlines[1].tags, vec![String::from("news"), String::from("open source")]);
+        assert_eq!(outlines[2].xml_url, "exec:~/bin/scrape.sh");
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].contains("filter feed"));
+    }
+
+    #[test]
+    fn urls_round_trip() {
+        let (outlines, _) = parse(URLS);
+        assert_eq!(parse(&render(&outlines)), (outlines, Vec::new()));
+    }
+}
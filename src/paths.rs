@@ -0,0 +1,100 @@
+//! Resolution of the directories and files koifeed uses, following the XDG base directory
+//! specification unless overridden.
+
+use std::env;
+
+/// The locations of the configuration directory, the OPML subscription list and the directory
+/// of cached feeds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paths {
+    /// The directory holding the OPML file and any other configuration.
+    pub config_dir: String,
+    /// The OPML subscription list.
+    pub opml: String,
+    /// The directory holding cached feeds and their bookkeeping.
+    pub data_dir: String,
+}
+
+impl Paths {
+    /// Resolves all paths from the process environment. Explicitly given paths, such as those
+    /// from command-line flags, take precedence over everything else.
+    pub fn resolve(opml: Option<&str>, data_dir: Option<&str>) -> Result<Self, String> {
+        Self::resolve_with(|key| env::var(key).ok(), opml, data_dir)
+    }
+
+    /// Resolves all paths with environment variables looked up by `var`. Each path is taken, in
+    /// order of precedence, from its explicit argument, its `KOIFEED_*` variable (`KOIFEED_OPML`,
+    /// `KOIFEED_DATA_DIR`, `KOIFEED_CONFIG_DIR`), the XDG base directory variable, or its default
+    /// under `HOME`. The OPML file defaults to `feeds.opml` in the configuration directory.
+    pub fn resolve_with<F>(var: F, opml: Option<&str>, data_dir: Option<&str>) -> Result<Self, String>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        // Empty variables count as unset, and XDG variables must hold absolute paths.
+        let var = |key: &str| var(key).filter(|value| !value.is_empty());
+        let xdg = |key: &str, default: &str| match var(key).filter(|value| value.starts_with('/')) {
+            Some(base) => Ok(base),
+            None => var("HOME")
+                .map(|home| format!("{}/{}", home, default))
+                .ok_or_else(|| format!("cannot locate koifeed's files: set HOME, {} or the KOIFEED_* variables", key)),
+        };
+
+        let opml = opml.map(String::from).or_else(|| var("KOIFEED_OPML"));
+        let config_dir = match (var("KOIFEED_CONFIG_DIR"), xdg("XDG_CONFIG_HOME", ".config"), &opml) {
+            (Some(dir), _, _) => dir,
+            (None, Ok(base), _) => format!("{}/koifeed", base),
+            // Without any other hint, configuration lives next to an explicitly given OPML file.
+            (None, Err(_), Some(opml)) => match opml.rfind('/') {
+                Some(0) => String::from("/"),
+                Some(slash) => String::from(&opml[..slash]),
+                None => String::from("."),
+            },
+            (None, Err(err), None) => return Err(err),
+        };
+        let opml = opml.unwrap_or_else(|| format!("{}/feeds.opml", config_dir));
+        let data_dir = match data_dir.map(String::from).or_else(|| var("KOIFEED_DATA_DIR")) {
+            Some(dir) => dir,
+            None => format!("{}/koifeed", xdg("XDG_DATA_HOME", ".local/share")?),
+        };
+        Ok(Self { config_dir, opml, data_dir })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve(vars: &[(&str, &str)], opml: Option<&str>, data_dir: Option<&str>) -> Result<Paths, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (String::from(*key), String::from(*value)))
+            .collect();
+        Paths::resolve_with(|key| vars.get(key).cloned(), opml, data_dir)
+    }
+
+    #[test]
+    fn paths_default_to_home() {
+        let paths = resolve(&[("HOME", "/home/ty")], None, None).unwrap();
+        assert_eq!(paths.opml, "/home/ty/.config/koifeed/feeds.opml");
+        assert_eq!(paths.data_dir, "/home/ty/.local/share/koifeed");
+    }
+
+    #[test]
+    fn paths_follow_xdg() {
+        let vars = [("HOME", "/home/ty"), ("XDG_CONFIG_HOME", "/etc/xdg"), ("XDG_DATA_HOME", "relative")];
+        let paths = resolve(&vars, None, None).unwrap();
+        assert_eq!(paths.opml, "/etc/xdg/koifeed/feeds.opml");
+        assert_eq!(paths.data_dir, "/home/ty/.local/share/koifeed");
+    }
+
+    #[test]
+    fn paths_overridden() {
+        let vars = [("KOIFEED_OPML", "/srv/feeds.opml"), ("KOIFEED_DATA_DIR", "/srv/cache")];
+        let paths = resolve(&vars, None, Some("/tmp/cache")).unwrap();
+        assert_eq!(paths.opml, "/srv/feeds.opml");
+        assert_eq!(paths.data_dir, "/tmp/cache");
+        assert_eq!(paths.config_dir, "/srv");
+        assert!(resolve(&[], None, None).is_err());
+    }
+}
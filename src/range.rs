@@ -0,0 +1,178 @@
+//! Date ranges used to filter entries by their parsed timestamps rather than by matching date
+//! strings.
+
+use crate::Entry;
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, Utc};
+
+/// One end of a `DateRange`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateBound {
+    /// A fixed point in time.
+    At(DateTime<Utc>),
+    /// The time of the update before the most recent one of whichever feed is being filtered,
+    /// so that a range starting here covers what the most recent update brought in.
+    LastUpdate,
+}
+
+impl DateBound {
+    /// Parses a bound given as a yyyy-mm-dd date, an RFC 3339 timestamp, a duration before `now` as
+    /// accepted by `parse_duration()`, or the keyword `last-update`. A bare date refers to the
+    /// start of that day (UTC), or to its end if `end_of_day` is set, so that ranges written with
+    /// dates include both of their ends.
+    pub fn parse(text: &str, now: DateTime<Utc>, end_of_day: bool) -> Result<Self, String> {
+        let text = text.trim();
+        if text == "last-update" {
+            return Ok(DateBound::LastUpdate);
+        }
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+            return Ok(DateBound::At(timestamp.with_timezone(&Utc)));
+        }
+        if let Ok(day) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+            let time = if end_of_day {
+                day.and_hms_opt(23, 59, 59)
+            } else {
+                day.and_hms_opt(0, 0, 0)
+            };
+            return time
+                .map(|time| DateBound::At(time.and_utc()))
+                .ok_or_else(|| format!("invalid date: {}", text));
+        }
+        parse_duration(text)
+            .ok()
+            .and_then(|duration| now.checked_sub_signed(duration))
+            .map(DateBound::At)
+            .ok_or_else(|| format!("invalid date or duration: {}", text))
+    }
+
+    /// Resolves the bound to a point in time. `DateBound::LastUpdate` resolves to `last_update`,
+    /// which is `None` for feeds that have not been updated twice.
+    pub fn resolve(&self, last_update: Option<DateTime<Utc>>) -> Option<DateTime<Utc>> {
+        match self {
+            DateBound::At(timestamp) => Some(*timestamp),
+            DateBound::LastUpdate => last_update,
+        }
+    }
+}
+
+/// An inclusive range of dates, either end of which may be left open.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DateRange {
+    /// The earliest date included.
+    pub since: Option<DateBound>,
+    /// The latest date included.
+    pub until: Option<DateBound>,
+}
+
+impl DateRange {
+    /// Checks whether no bound is set at all.
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    /// Checks whether a timestamp falls inside the range, resolving `DateBound::LastUpdate`
+    /// against `last_update`. Bounds which do not resolve are treated as open. Entries without a
+    /// timestamp are only inside an unbounded range.
+    pub fn contains(&self, timestamp: Option<DateTime<FixedOffset>>, last_update: Option<DateTime<Utc>>) -> bool {
+        if self.is_unbounded() {
+            return true;
+        }
+        let timestamp = match timestamp {
+            Some(timestamp) => timestamp.with_timezone(&Utc),
+            None => return false,
+        };
+        let after_since = match self.since.and_then(|bound| bound.resolve(last_update)) {
+            Some(since) => timestamp >= since,
+            None => true,
+        };
+        let before_until = match self.until.and_then(|bound| bound.resolve(last_update)) {
+            Some(until) => timestamp <= until,
+            None => true,
+        };
+        after_since && before_until
+    }
+
+    /// Returns the first of `entries` inside the range, such as the newest entry of a feed to
+    /// show in a listing of feeds. Entries outside the range are passed over rather than ending
+    /// the search, so that a feed whose latest entries are too new still shows its newest one
+    /// inside the range.
+    pub fn first(&self, entries: impl IntoIterator<Item = Entry>, last_update: Option<DateTime<Utc>>) -> Option<Entry> {
+        entries
+            .into_iter()
+            .find(|entry| self.contains(entry.timestamp(), last_update))
+    }
+}
+
+/// Parses a duration given as a whole number of minutes, hours, days or weeks, such as `30m`,
+/// `12h`, `3d` or `2w`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text.len() - text.chars().last().map_or(0, char::len_utf8);
+    let (amount, unit) = text.split_at(split);
+    let amount = amount
+        .parse::<i64>()
+        .map_err(|_| format!("invalid duration: {}", text))?;
+    if amount < 0 {
+        return Err(format!("invalid duration: {} (must not be negative)", text));
+    }
+    let duration = match unit {
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    };
+    duration.ok_or_else(|| format!("invalid duration: {}", text))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::now;
+    use crate::date_parse_timestamp;
+
+    #[test]
+    fn bound_relative() {
+        let bound = DateBound::parse("3d", now(), false).unwrap();
+        let expected = DateTime::parse_from_rfc3339("2021-08-07T12:00:00Z").unwrap();
+        assert_eq!(bound, DateBound::At(expected.with_timezone(&Utc)));
+    }
+
+    #[test]
+    fn bound_invalid() {
+        assert!(DateBound::parse("3x", now(), false).is_err());
+        assert!(DateBound::parse("yesterday", now(), false).is_err());
+        assert!(DateBound::parse("-3d", now(), false).is_err());
+    }
+
+    #[test]
+    fn range_dates_inclusive() {
+        let range = DateRange {
+            since: Some(DateBound::parse("2021-08-06", now(), false).unwrap()),
+            until: Some(DateBound::parse("2021-08-06", now(), true).unwrap()),
+        };
+        assert!(range.contains(date_parse_timestamp("2021-08-06T15:32:35Z"), None));
+        assert!(!range.contains(date_parse_timestamp("2021-08-07T00:00:01Z"), None));
+        assert!(!range.contains(None, None));
+    }
+
+    #[test]
+    fn range_first_inside() {
+        let range = DateRange {
+            since: None,
+            until: Some(DateBound::parse("2021-08-07", now(), true).unwrap()),
+        };
+        let entry = |date: &str| Entry { date: Some(String::from(date)), ..Entry::default() };
+        let entries = vec![entry("2021-08-09"), entry("2021-08-06"), entry("2021-08-01")];
+        assert_eq!(range.first(entries.clone(), None), Some(entry("2021-08-06")));
+        assert_eq!(DateRange::default().first(entries, None), Some(entry("2021-08-09")));
+        assert_eq!(range.first(vec![entry("2021-08-09")], None), None);
+    }
+
+    #[test]
+    fn range_last_update() {
+        let range = DateRange { since: Some(DateBound::LastUpdate), until: None };
+        let timestamp = date_parse_timestamp("2021-08-06T15:32:35Z");
+        assert!(range.contains(timestamp, None));
+        assert!(!range.contains(timestamp, Some(now())));
+    }
+}
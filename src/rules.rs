@@ -0,0 +1,113 @@
+//! Rules hiding unwanted entries, like the kill files of news readers, as configured under
+//! `[[rules]]` in `config.toml`:
+//!
+//! ```toml
+//! [[rules]]
+//! feeds = ["npr"]
+//! title = "sponsored|promoted"
+//!
+//! [[rules]]
+//! tags = ["news"]
+//! older_than = "30d"
+//! ```
+//!
+//! A rule applies to the feeds it names and to those with any of its tags, or to all feeds if it
+//! names neither. It hides those entries of them which meet all of its conditions.
+
+use crate::config::Rule;
+use crate::range::parse_duration;
+use crate::Entry;
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+
+struct CompiledRule {
+    feeds: Vec<String>,
+    tags: Vec<String>,
+    title: Option<Regex>,
+    content: Option<Regex>,
+    before: Option<DateTime<Utc>>,
+}
+
+/// A set of rules, ready to be applied.
+#[derive(Default)]
+pub struct Rules(Vec<CompiledRule>);
+
+impl Rules {
+    /// Compiles rules, resolving ages such as `30d` relative to `now`. Patterns are regular
+    /// expressions which, as in searches, ignore case unless they turn it back on with `(?-i)`.
+    pub fn new(rules: &[Rule], now: DateTime<Utc>) -> Result<Self, String> {
+        let regex = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|pattern| RegexBuilder::new(pattern).case_insensitive(true).build())
+                .transpose()
+                .map_err(|err| err.to_string())
+        };
+        let mut compiled = Vec::new();
+        for (number, rule) in rules.iter().enumerate() {
+            let context = |err: String| format!("rules[{}]: {}", number, err);
+            if rule.title.is_none() && rule.content.is_none() && rule.older_than.is_none() {
+                return Err(context(String::from("a rule needs at least one of title, content and older_than")));
+            }
+            let before = match &rule.older_than {
+                Some(age) => Some(now - parse_duration(age).map_err(context)?),
+                None => None,
+            };
+            compiled.push(CompiledRule {
+                feeds: rule.feeds.clone(),
+                tags: rule.tags.clone(),
+                title: regex(&rule.title).map_err(context)?,
+                content: regex(&rule.content).map_err(context)?,
+                before,
+            });
+        }
+        Ok(Self(compiled))
+    }
+
+    /// Checks whether any rule hides an entry of the feed titled `feed`, which has the tags
+    /// `tags`.
+    pub fn hides(&self, feed: &str, tags: &[String], entry: &Entry) -> bool {
+        self.0.iter().any(|rule| {
+            let applies = (rule.feeds.is_empty() && rule.tags.is_empty())
+                || rule.feeds.iter().any(|title| title == feed)
+                || rule.tags.iter().any(|tag| tags.contains(tag));
+            applies
+                && rule.title.as_ref().is_none_or(|title| title.is_match(&entry.title))
+                && rule.content.as_ref().is_none_or(|content| content.is_match(&entry.content))
+                && rule.before.is_none_or(|before| {
+                    entry.timestamp().is_some_and(|timestamp| timestamp.with_timezone(&Utc) < before)
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::now;
+
+    fn entry(title: &str, date: &str) -> Entry {
+        Entry { title: String::from(title), date: Some(String::from(date)), ..Entry::default() }
+    }
+
+    #[test]
+    fn rules_hide() {
+        let rules = Rules::new(&[
+            Rule { feeds: vec![String::from("npr")], title: Some(String::from("sponsored")), ..Rule::default() },
+            Rule { tags: vec![String::from("news")], older_than: Some(String::from("30d")), ..Rule::default() },
+        ], now()).unwrap();
+        let news = [String::from("news")];
+        assert!(rules.hides("npr", &[], &entry("A Sponsored Post", "2021-08-09")));
+        assert!(!rules.hides("neovim", &[], &entry("A Sponsored Post", "2021-08-09")));
+        assert!(rules.hides("slashdot", &news, &entry("Old News", "2021-06-01")));
+        assert!(!rules.hides("slashdot", &news, &entry("New News", "2021-08-01")));
+        assert!(!rules.hides("slashdot", &[], &entry("Old News", "2021-06-01")));
+    }
+
+    #[test]
+    fn rules_invalid() {
+        assert!(Rules::new(&[Rule { feeds: vec![String::from("npr")], ..Rule::default() }], now()).is_err());
+        assert!(Rules::new(&[Rule { title: Some(String::from("(")), ..Rule::default() }], now()).is_err());
+        assert!(Rules::new(&[Rule { older_than: Some(String::from("old")), ..Rule::default() }], now()).is_err());
+    }
+}
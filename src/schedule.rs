@@ -0,0 +1,117 @@
+//! When feeds are due for their next update. Publishers can ask readers to poll less often, with
+//! RSS `<ttl>`, the syndication module's `sy:updatePeriod` and `sy:updateFrequency`, or HTTP
+//! caching headers; koifeed follows the longest of these hints, capped at `MAX_HINT`.
+
+use crate::{Feed, KEYS_ENTRY};
+use chrono::{DateTime, Duration, Utc};
+use xml::reader::{EventReader, XmlEvent};
+
+/// The longest interval a publisher's hint can delay an update by, so that a misconfigured
+/// server cannot stop a feed from updating indefinitely.
+pub const MAX_HINT: Duration = Duration::weeks(1);
+
+/// Returns the interval the feed itself asks readers to wait between updates, if any.
+pub fn feed_interval(feed: &Feed) -> Option<Duration> {
+    let mut ttl = None;
+    let mut period = None;
+    let mut frequency = None;
+    let mut current: Option<String> = None;
+    for event in EventReader::new(feed.0.as_bytes()) {
+        match event {
+            Ok(XmlEvent::StartElement { name, .. }) => {
+                // Only channel-level elements count; entries come after them.
+                if KEYS_ENTRY.contains(&&name.local_name[..]) {
+                    break;
+                }
+                current = Some(name.local_name);
+            },
+            Ok(XmlEvent::Characters(text)) => {
+                let text = text.trim();
+                match current.as_deref() {
+                    Some("ttl") => ttl = text.parse::<i64>().ok().and_then(Duration::try_minutes),
+                    Some("updatePeriod") => period = match text {
+                        "hourly" => Some(Duration::hours(1)),
+                        "daily" => Some(Duration::days(1)),
+                        "weekly" => Some(Duration::weeks(1)),
+                        "monthly" => Some(Duration::days(30)),
+                        "yearly" => Some(Duration::days(365)),
+                        _ => None,
+                    },
+                    Some("updateFrequency") => frequency = text.parse::<i32>().ok().filter(|n| *n > 0),
+                    _ => {},
+                }
+            },
+            Ok(XmlEvent::EndElement { .. }) => current = None,
+            Err(_) => break,
+            _ => {},
+        }
+    }
+    // The syndication module defaults to once a day when only one of its elements is given.
+    let syndication = match (period, frequency) {
+        (None, None) => None,
+        (period, frequency) => Some(period.unwrap_or_else(|| Duration::days(1)) / frequency.unwrap_or(1)),
+    };
+    ttl.into_iter().chain(syndication).max()
+}
+
+/// Returns how long a response stays fresh according to the values of its `Cache-Control` and
+/// `Expires` headers, the former taking precedence. Responses marked `no-cache` or `no-store`
+/// are never fresh.
+pub fn cache_lifetime(cache_control: Option<&str>, expires: Option<&str>, now: DateTime<Utc>) -> Option<Duration> {
+    if let Some(cache_control) = cache_control {
+        for directive in cache_control.split(',').map(str::trim) {
+            let directive = directive.to_ascii_lowercase();
+            if directive == "no-cache" || directive == "no-store" {
+                return Some(Duration::zero());
+            }
+            if let Some(seconds) = directive.strip_prefix("max-age=") {
+                if let Some(lifetime) = seconds.trim_matches('"').parse::<i64>().ok().and_then(Duration::try_seconds) {
+                    return Some(lifetime);
+                }
+            }
+        }
+    }
+    let expires = DateTime::parse_from_rfc2822(expires?.trim()).ok()?;
+    Some((expires.with_timezone(&Utc) - now).max(Duration::zero()))
+}
+
+/// Returns the time after which a feed fetched at `now` should next be updated, given the hints
+/// from the feed and its response.
+pub fn next_due(now: DateTime<Utc>, hints: &[Option<Duration>]) -> Option<DateTime<Utc>> {
+    hints
+        .iter()
+        .flatten()
+        .max()
+        .map(|hint| now + (*hint).min(MAX_HINT))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::now;
+
+    #[test]
+    fn interval_from_feed() {
+        let feed = Feed::new(String::from(r#"<rss xmlns:sy="http://purl.org/rss/1.0/modules/syndication/">
+            <channel><ttl>60</ttl><sy:updatePeriod>daily</sy:updatePeriod><sy:updateFrequency>4</sy:updateFrequency>
+            <item><ttl>9999</ttl></item></channel></rss>"#));
+        assert_eq!(feed_interval(&feed), Some(Duration::hours(6)));
+        assert_eq!(feed_interval(&Feed::new(String::from("<rss><channel/></rss>"))), None);
+    }
+
+    #[test]
+    fn lifetime_from_headers() {
+        assert_eq!(cache_lifetime(Some("public, max-age=600"), None, now()), Some(Duration::minutes(10)));
+        assert_eq!(cache_lifetime(Some("no-cache"), None, now()), Some(Duration::zero()));
+        let expires = Some("Tue, 10 Aug 2021 14:00:00 GMT");
+        assert_eq!(cache_lifetime(None, expires, now()), Some(Duration::hours(2)));
+        assert_eq!(cache_lifetime(None, None, now()), None);
+    }
+
+    #[test]
+    fn due_capped() {
+        assert_eq!(next_due(now(), &[None, None]), None);
+        assert_eq!(next_due(now(), &[Some(Duration::hours(1)), Some(Duration::hours(2))]), Some(now() + Duration::hours(2)));
+        assert_eq!(next_due(now(), &[Some(Duration::weeks(52))]), Some(now() + MAX_HINT));
+    }
+}
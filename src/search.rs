@@ -0,0 +1,108 @@
+//! Matching of entries against a search query, by case-insensitive substring or by regular
+//! expression.
+
+use crate::Entry;
+use regex::{Regex, RegexBuilder};
+use std::str::FromStr;
+
+/// The parts of an entry which a query is matched against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    /// Only the title.
+    Title,
+    /// Only the main content or description.
+    Content,
+    /// Both the title and the content.
+    All,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "title" => Ok(Field::Title),
+            "content" => Ok(Field::Content),
+            "all" => Ok(Field::All),
+            _ => Err(format!("invalid search field: {}", text)),
+        }
+    }
+}
+
+enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+/// A search query over entries.
+pub struct Query {
+    field: Field,
+    pattern: Pattern,
+}
+
+impl Query {
+    /// A query matching entries which contain `text`, ignoring case.
+    pub fn substring(text: &str, field: Field) -> Self {
+        Self { field, pattern: Pattern::Substring(text.to_lowercase()) }
+    }
+
+    /// A query matching entries in which the regular expression `text` finds a match. Matching
+    /// ignores case unless the expression turns it back on with `(?-i)`.
+    pub fn regex(text: &str, field: Field) -> Result<Self, regex::Error> {
+        let regex = RegexBuilder::new(text)
+            .case_insensitive(true)
+            .build()?;
+        Ok(Self { field, pattern: Pattern::Regex(regex) })
+    }
+
+    /// Checks whether the query matches the selected fields of an entry.
+    pub fn matches(&self, entry: &Entry) -> bool {
+        match self.field {
+            Field::Title => self.matches_text(&entry.title),
+            Field::Content => self.matches_text(&entry.content),
+            Field::All => self.matches_text(&entry.title) || self.matches_text(&entry.content),
+        }
+    }
+
+    /// The lowercased text of a substring query.
+    pub(crate) fn substring_text(&self) -> Option<&str> {
+        match &self.pattern {
+            Pattern::Substring(substring) => Some(substring),
+            Pattern::Regex(_) => None,
+        }
+    }
+
+    fn matches_text(&self, text: &str) -> bool {
+        match &self.pattern {
+            Pattern::Substring(substring) => text.to_lowercase().contains(substring),
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            title: String::from("Atom-Powered Robots Run Amok"),
+            content: String::from("Some text."),
+            ..Entry::default()
+        }
+    }
+
+    #[test]
+    fn substring_ignores_case() {
+        assert!(Query::substring("robots", Field::Title).matches(&entry()));
+        assert!(!Query::substring("robots", Field::Content).matches(&entry()));
+        assert!(Query::substring("SOME", Field::All).matches(&entry()));
+    }
+
+    #[test]
+    fn regex_matches() {
+        assert!(Query::regex(r"run\s+amok$", Field::Title).unwrap().matches(&entry()));
+        assert!(!Query::regex(r"(?-i)run amok", Field::Title).unwrap().matches(&entry()));
+        assert!(Query::regex("(", Field::All).is_err());
+    }
+}
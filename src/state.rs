@@ -0,0 +1,160 @@
+//! Per-feed bookkeeping kept next to the cached feeds, in a `.state` directory of plain
+//! `key=value` files named by feed title.
+
+use chrono::{DateTime, Duration, Utc};
+use std::fs;
+use std::io::{self, ErrorKind};
+
+/// What koifeed remembers about a single feed between runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeedState {
+    /// Time of the most recent successful update.
+    pub updated: Option<DateTime<Utc>>,
+    /// Time of the successful update before that.
+    pub updated_previous: Option<DateTime<Utc>>,
+    /// Time before which the publisher asked not to be polled again.
+    pub due: Option<DateTime<Utc>>,
+    /// Number of failed updates since the last successful one.
+    pub failures: u32,
+    /// Time of the most recent failed update.
+    pub failed: Option<DateTime<Utc>>,
+    /// Why the most recent failed update failed.
+    pub error: Option<String>,
+    /// The HTTP status code of the most recent response, if there was one.
+    pub status: Option<u16>,
+    /// The URL the feed permanently redirected to when it was last updated.
+    pub moved: Option<String>,
+}
+
+impl FeedState {
+    /// Reads the state of the feed titled `title` from the feed directory `dir_path`. Feeds
+    /// without any recorded state get the default, empty state.
+    pub fn load(dir_path: &str, title: &str) -> io::Result<Self> {
+        let text = match fs::read_to_string(Self::path(dir_path, title)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        Ok(Self::parse(&text))
+    }
+
+    /// Writes the state of the feed titled `title` into the feed directory `dir_path`.
+    pub fn save(&self, dir_path: &str, title: &str) -> io::Result<()> {
+        fs::create_dir_all(format!("{}/.state", dir_path))?;
+        fs::write(Self::path(dir_path, title), self.serialize())
+    }
+
+    /// Notes a successful update at time `now`.
+    pub fn record_update(&mut self, now: DateTime<Utc>) {
+        self.updated_previous = self.updated;
+        self.updated = Some(now);
+        self.failures = 0;
+    }
+
+    /// Notes a failed update at time `now`. Line breaks in `error` are folded into spaces.
+    pub fn record_failure(&mut self, now: DateTime<Utc>, error: &str) {
+        self.failures += 1;
+        self.failed = Some(now);
+        self.error = Some(error.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    /// Returns the time after which the feed should next be updated, honouring both the
+    /// publisher's hints and a configured minimum `interval` between updates.
+    pub fn next_due(&self, interval: Option<Duration>) -> Option<DateTime<Utc>> {
+        let configured = self.updated.zip(interval).map(|(updated, interval)| updated + interval);
+        self.due.into_iter().chain(configured).max()
+    }
+
+    fn path(dir_path: &str, title: &str) -> String {
+        format!("{}/.state/{}", dir_path, title)
+    }
+
+    /// Unknown keys and malformed values are skipped, so that state written by other versions
+    /// never prevents koifeed from running.
+    fn parse(text: &str) -> Self {
+        let mut state = Self::default();
+        for line in text.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let timestamp = || {
+                DateTime::parse_from_rfc3339(value)
+                    .ok()
+                    .map(|timestamp| timestamp.with_timezone(&Utc))
+            };
+            match key {
+                "updated" => state.updated = timestamp(),
+                "updated_previous" => state.updated_previous = timestamp(),
+                "due" => state.due = timestamp(),
+                "failures" => state.failures = value.parse().unwrap_or_default(),
+                "failed" => state.failed = timestamp(),
+                "error" => state.error = Some(String::from(value)),
+                "status" => state.status = value.parse().ok(),
+                "moved" => state.moved = Some(String::from(value)),
+                _ => {},
+            }
+        }
+        state
+    }
+
+    fn serialize(&self) -> String {
+        let mut text = String::new();
+        let timestamps = [
+            ("updated", self.updated),
+            ("updated_previous", self.updated_previous),
+            ("due", self.due),
+            ("failed", self.failed),
+        ];
+        for (key, timestamp) in timestamps.iter() {
+            if let Some(timestamp) = timestamp {
+                text.push_str(&format!("{}={}\n", key, timestamp.to_rfc3339()));
+            }
+        }
+        if self.failures > 0 {
+            text.push_str(&format!("failures={}\n", self.failures));
+        }
+        if let Some(status) = self.status {
+            text.push_str(&format!("status={}\n", status));
+        }
+        if let Some(moved) = &self.moved {
+            text.push_str(&format!("moved={}\n", moved));
+        }
+        if let Some(error) = &self.error {
+            text.push_str(&format!("error={}\n", error));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn state_round_trip() {
+        let mut state = FeedState::default();
+        let now = DateTime::parse_from_rfc3339("2021-08-06T15:32:35Z").unwrap();
+        state.record_update(now.with_timezone(&Utc));
+        state.record_update(now.with_timezone(&Utc));
+        state.due = Some(now.with_timezone(&Utc) + Duration::hours(1));
+        state.record_failure(now.with_timezone(&Utc), "timed out\nafter 30s");
+        state.status = Some(503);
+        state.moved = Some(String::from("https://example.com/feed?a=1&b=2"));
+        assert_eq!(state.error.as_deref(), Some("timed out after 30s"));
+        assert_eq!(FeedState::parse(&state.serialize()), state);
+        state.record_update(now.with_timezone(&Utc));
+        assert_eq!(state.failures, 0);
+    }
+
+    #[test]
+    fn state_next_due() {
+        let now = DateTime::parse_from_rfc3339("2021-08-06T15:32:35Z").unwrap().with_timezone(&Utc);
+        let mut state = FeedState::default();
+        assert_eq!(state.next_due(Some(Duration::hours(6))), None);
+        state.record_update(now);
+        state.due = Some(now + Duration::hours(1));
+        assert_eq!(state.next_due(None), state.due);
+        assert_eq!(state.next_due(Some(Duration::hours(6))), Some(now + Duration::hours(6)));
+    }
+}